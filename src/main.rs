@@ -41,25 +41,11 @@ fn main() {
     #[cfg(feature = "inspector")]
     app.add_plugins(bevy_inspector_egui::quick::WorldInspectorPlugin::new());
 
-    #[cfg(debug_assertions)]
-    {
-        app.add_plugins(bevy_pancam::PanCamPlugin);
-    }
-
     app.run();
 }
 
 #[derive(Component, Default)]
 struct GameCamera;
 fn setup(mut cmds: Commands) {
-    cmds.spawn((
-        Camera2dBundle::default(),
-        #[cfg(debug_assertions)]
-        bevy_pancam::PanCam {
-            grab_buttons: vec![MouseButton::Middle],
-            enabled: true,
-            ..default()
-        },
-        GameCamera,
-    ));
+    cmds.spawn((Camera2dBundle::default(), GameCamera));
 }
@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{
+    ball::Ball,
+    momentum::Momentum,
+    player::{Facing, Player},
+    settings::DisplaySettings,
+    Dir,
+};
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (animate_player, animate_balls, play_animations).chain(),
+        );
+    }
+}
+
+/// The walk cycle keeps playing this long after a step, so holding a direction
+/// doesn't flicker back to idle between steps
+const WALK_LINGER_SECONDS: f32 = 0.15;
+/// Shortest time a frame is shown
+const MIN_FRAME_SECONDS: f32 = 0.01;
+
+/// Frames of a sprite sheet that are played one after another
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Clip {
+    /// Indices into the sprite sheet, frames are numbered row by row
+    pub frames: Vec<usize>,
+    /// How long each frame is shown
+    pub frame_seconds: f32,
+}
+
+impl Default for Clip {
+    fn default() -> Self {
+        Self {
+            frames: vec![0],
+            frame_seconds: 1.,
+        }
+    }
+}
+
+/// A value for every direction something can face
+#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct Directional<T> {
+    pub up: T,
+    pub right: T,
+    pub down: T,
+    pub left: T,
+}
+
+impl<T> Directional<T> {
+    pub fn get(&self, dir: Dir) -> &T {
+        match dir {
+            Dir::Up => &self.up,
+            Dir::Right => &self.right,
+            Dir::Down => &self.down,
+            Dir::Left => &self.left,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        [&self.up, &self.right, &self.down, &self.left].into_iter()
+    }
+}
+
+/// Clips of the player, kept on the player so they always match the sheet it was spawned with
+#[derive(Component, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct PlayerAnimations {
+    pub idle: Directional<Clip>,
+    pub walk: Directional<Clip>,
+}
+
+impl PlayerAnimations {
+    pub fn clips(&self) -> impl Iterator<Item = &Clip> {
+        self.idle.iter().chain(self.walk.iter())
+    }
+}
+
+/// Clips of a ball, kept on the ball like `PlayerAnimations`
+#[derive(Component, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct BallAnimations {
+    pub idle: Clip,
+    /// Loops while the ball has momentum
+    pub roll: Clip,
+    /// Played once when the ball comes to rest
+    pub squash: Clip,
+}
+
+impl BallAnimations {
+    pub fn clips(&self) -> impl Iterator<Item = &Clip> {
+        [&self.idle, &self.roll, &self.squash].into_iter()
+    }
+}
+
+/// Plays a clip on the `TextureAtlasSprite` of the entity
+#[derive(Component, Default)]
+pub struct SpriteAnimation {
+    clip: Clip,
+    looping: bool,
+    frame: usize,
+    timer: Timer,
+    finished: bool,
+}
+
+impl SpriteAnimation {
+    /// Starts playing `clip` from the first frame, unless it is playing already
+    pub fn play(&mut self, clip: &Clip, looping: bool) {
+        if self.clip == *clip && self.looping == looping {
+            return;
+        }
+        self.clip = clip.clone();
+        self.looping = looping;
+        self.frame = 0;
+        self.finished = false;
+        self.timer = Timer::from_seconds(
+            clip.frame_seconds.max(MIN_FRAME_SECONDS),
+            TimerMode::Repeating,
+        );
+    }
+
+    /// A clip that doesn't loop has shown its last frame
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    fn advance(&mut self, delta: Duration) {
+        let steps = self.timer.tick(delta).times_finished_this_tick();
+        for _ in 0..steps {
+            if self.frame + 1 < self.clip.frames.len() {
+                self.frame += 1;
+            } else if self.looping {
+                self.frame = 0;
+            } else {
+                self.finished = true;
+            }
+        }
+    }
+
+    fn index(&self) -> Option<usize> {
+        self.clip.frames.get(self.frame).copied()
+    }
+}
+
+/// What a ball is animating
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BallPose {
+    #[default]
+    Resting,
+    Rolling,
+    Squashing,
+}
+
+fn animate_player(
+    mut query: Query<(&Facing, &PlayerAnimations, &mut SpriteAnimation), With<Player>>,
+    time: Res<Time>,
+) {
+    for (facing, animations, mut animation) in query.iter_mut() {
+        let walking = time.elapsed_seconds() - facing.moved_at < WALK_LINGER_SECONDS;
+        let clips = if walking {
+            &animations.walk
+        } else {
+            &animations.idle
+        };
+        animation.play(clips.get(facing.dir), true);
+    }
+}
+
+fn animate_balls(
+    mut query: Query<
+        (
+            &Momentum,
+            &BallAnimations,
+            &mut BallPose,
+            &mut SpriteAnimation,
+        ),
+        With<Ball>,
+    >,
+    display: Res<DisplaySettings>,
+) {
+    for (momentum, clips, mut pose, mut animation) in query.iter_mut() {
+        let next = match *pose {
+            _ if momentum.is_some() => BallPose::Rolling,
+            BallPose::Rolling if !display.reduced_motion => BallPose::Squashing,
+            BallPose::Squashing if !animation.finished() => BallPose::Squashing,
+            _ => BallPose::Resting,
+        };
+        if *pose != next {
+            *pose = next;
+        }
+        match next {
+            BallPose::Resting => animation.play(&clips.idle, true),
+            BallPose::Rolling => animation.play(&clips.roll, true),
+            BallPose::Squashing => animation.play(&clips.squash, false),
+        }
+    }
+}
+
+fn play_animations(
+    mut query: Query<(&mut SpriteAnimation, &mut TextureAtlasSprite)>,
+    time: Res<Time>,
+) {
+    for (mut animation, mut sprite) in query.iter_mut() {
+        animation.advance(time.delta());
+        if let Some(index) = animation.index() {
+            if sprite.index != index {
+                sprite.index = index;
+            }
+        }
+    }
+}
+
+/// How an entity is drawn, to draw copies of it
+#[derive(Clone)]
+pub enum SpriteLook {
+    Image(Handle<Image>),
+    Sheet {
+        texture_atlas: Handle<TextureAtlas>,
+        index: usize,
+    },
+}
+
+impl SpriteLook {
+    pub fn of(world: &mut World, entity: Entity) -> Option<SpriteLook> {
+        let (texture, sheet) = world
+            .query::<(
+                Option<&Handle<Image>>,
+                Option<(&Handle<TextureAtlas>, &TextureAtlasSprite)>,
+            )>()
+            .get(world, entity)
+            .ok()?;
+        match (texture, sheet) {
+            (_, Some((texture_atlas, sprite))) => Some(SpriteLook::Sheet {
+                texture_atlas: texture_atlas.clone(),
+                index: sprite.index,
+            }),
+            (Some(texture), None) => Some(SpriteLook::Image(texture.clone())),
+            (None, None) => None,
+        }
+    }
+}
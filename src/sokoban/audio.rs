@@ -1,7 +1,7 @@
-use bevy::{audio::*, prelude::*};
+use bevy::{audio::*, prelude::*, utils::HashMap};
 use bevy_asset_loader::prelude::AssetCollection;
 
-use super::{GameState, SokobanEvent};
+use super::SokobanEvent;
 
 pub struct GameAudioPlugin;
 
@@ -10,12 +10,9 @@ impl Plugin for GameAudioPlugin {
         app.register_type::<AudioCollection>()
             .register_type::<VolumeSettings>()
             .init_resource::<VolumeSettings>()
-            .add_systems(
-                Update,
-                handle_audio
-                    .run_if(in_state(GameState::Play))
-                    .run_if(on_event::<SokobanEvent>()),
-            );
+            .init_resource::<SfxCooldowns>()
+            // Not limited to play, the level is already left once `LevelComplete` is handled
+            .add_systems(Update, handle_audio.run_if(on_event::<SokobanEvent>()));
     }
 }
 
@@ -38,11 +35,12 @@ impl Default for VolumeSettings {
 }
 
 impl VolumeSettings {
-    pub fn change_sfx_vol(&mut self) {
-        self.sfx_vol = (self.sfx_vol + self.increment).clamp(0., 1.);
+    /// Raises the volume by `steps` increments, negative steps lower it
+    pub fn change_sfx_vol(&mut self, steps: f32) {
+        self.sfx_vol = (self.sfx_vol + self.increment * steps).clamp(0., 1.);
     }
-    pub fn change_music_vol(&mut self) {
-        self.music_vol = (self.music_vol + self.increment).clamp(0., 1.);
+    pub fn change_music_vol(&mut self, steps: f32) {
+        self.music_vol = (self.music_vol + self.increment * steps).clamp(0., 1.);
     }
 }
 
@@ -59,32 +57,77 @@ pub struct AudioCollection {
     pub wall: Handle<AudioSource>,
     #[asset(path = "void.wav")]
     pub void: Handle<AudioSource>,
+    #[asset(path = "rubber.wav")]
+    pub rubber: Handle<AudioSource>,
+    #[asset(path = "sand.wav")]
+    pub sand: Handle<AudioSource>,
+    #[asset(path = "lamp.wav")]
+    pub lamp: Handle<AudioSource>,
+    #[asset(path = "complete.wav")]
+    pub complete: Handle<AudioSource>,
 }
 
+impl AudioCollection {
+    fn sound(&self, event: SokobanEvent) -> Handle<AudioSource> {
+        match event {
+            SokobanEvent::PlayerMoved => self.walk.clone(),
+            SokobanEvent::PlayerPush => self.push_player.clone(),
+            SokobanEvent::BallPush => self.push_ball.clone(),
+            SokobanEvent::BallHitWall => self.wall.clone(),
+            SokobanEvent::EntityInVoid => self.void.clone(),
+            SokobanEvent::RubberBounce => self.rubber.clone(),
+            SokobanEvent::SandStop => self.sand.clone(),
+            SokobanEvent::LampToggled => self.lamp.clone(),
+            SokobanEvent::LevelComplete => self.complete.clone(),
+        }
+    }
+}
+
+/// Seconds that have to pass before the same sound is played again,
+/// so chain reactions don't stack dozens of voices
+fn cooldown(event: SokobanEvent) -> f32 {
+    match event {
+        SokobanEvent::PlayerMoved | SokobanEvent::PlayerPush => 0.05,
+        SokobanEvent::BallPush
+        | SokobanEvent::BallHitWall
+        | SokobanEvent::RubberBounce
+        | SokobanEvent::SandStop
+        | SokobanEvent::LampToggled => 0.1,
+        SokobanEvent::EntityInVoid => 0.2,
+        SokobanEvent::LevelComplete => 1.,
+    }
+}
+
+/// When each sound was last played
+#[derive(Resource, Default)]
+struct SfxCooldowns(HashMap<SokobanEvent, f32>);
+
 fn handle_audio(
     mut cmds: Commands,
     mut sokoban_events: EventReader<SokobanEvent>,
+    mut cooldowns: ResMut<SfxCooldowns>,
     audio: Res<AudioCollection>,
     volume_settings: Res<VolumeSettings>,
+    time: Res<Time<Real>>,
 ) {
     let settings = PlaybackSettings {
         mode: PlaybackMode::Despawn,
         volume: Volume::Absolute(VolumeLevel::new(volume_settings.sfx_vol)),
         ..default()
     };
+    let now = time.elapsed_seconds();
     for ev in sokoban_events.read() {
-        match ev {
-            SokobanEvent::PlayerMoved => cmds.spawn(AudioBundle {
-                source: audio.walk.clone(),
-                settings,
-            }),
-            SokobanEvent::PlayerPush => cmds.spawn(AudioBundle {
-                source: audio.push_player.clone(),
-                settings,
-            }),
-            SokobanEvent::BallPush => todo!(),
-            SokobanEvent::BallHitWall => todo!(),
-            SokobanEvent::EntityInVoid => todo!(),
-        };
+        let ready = cooldowns
+            .0
+            .get(ev)
+            .map_or(true, |last| now - last >= cooldown(*ev));
+        if !ready {
+            continue;
+        }
+        cooldowns.0.insert(*ev, now);
+        cmds.spawn(AudioBundle {
+            source: audio.sound(*ev),
+            settings,
+        });
     }
 }
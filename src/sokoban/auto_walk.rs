@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+
+use bevy::{log, prelude::*, utils::HashMap, window::PrimaryWindow};
+
+use super::{
+    collision::CollisionMap,
+    grid::GridConfig,
+    momentum::any_momentum_left,
+    player::{player_movement, Player},
+    tile_behaviour::{Ice, Void},
+    Dir, GameState, Pos,
+};
+
+pub struct AutoWalkPlugin;
+
+impl Plugin for AutoWalkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AutoWalk>()
+            .add_systems(OnExit(GameState::Play), clear_auto_walk)
+            .add_systems(
+                Update,
+                (
+                    cancel_auto_walk.before(player_movement),
+                    click_to_move
+                        .after(cancel_auto_walk)
+                        .before(player_movement)
+                        .run_if(not(any_momentum_left())),
+                )
+                    .run_if(in_state(GameState::Play)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AutoStep {
+    pub dir: Dir,
+    /// Walking steps are dropped instead of pushing whatever got in the way
+    pub push: bool,
+}
+
+/// Steps the player takes on their own, one per movement step
+#[derive(Resource, Default, Debug)]
+pub struct AutoWalk(VecDeque<AutoStep>);
+
+impl AutoWalk {
+    pub fn start(&mut self, path: impl IntoIterator<Item = Dir>) {
+        self.0 = path
+            .into_iter()
+            .map(|dir| AutoStep { dir, push: false })
+            .collect();
+    }
+
+    /// Queues a push after the current walk
+    pub fn then_push(&mut self, dir: Dir) {
+        self.0.push_back(AutoStep { dir, push: true });
+    }
+
+    pub fn pop(&mut self) -> Option<AutoStep> {
+        self.0.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn is_walking(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+/// Breadth first search for the shortest path between two cells
+pub fn find_path(start: IVec2, goal: IVec2, passable: impl Fn(IVec2) -> bool) -> Option<Vec<Dir>> {
+    let mut came_from = HashMap::default();
+    came_from.insert(start, None);
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        if current == goal {
+            let mut path = Vec::new();
+            let mut pos = goal;
+            while let Some(Some(dir)) = came_from.get(&pos) {
+                path.push(*dir);
+                pos -= IVec2::from(*dir);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for dir in Dir::ALL {
+            let next = current + IVec2::from(dir);
+            if !came_from.contains_key(&next) && passable(next) {
+                came_from.insert(next, Some(dir));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// Floor the player would slide on or fall into
+pub type HazardQuery<'w, 's> = Query<'w, 's, &'static Pos, Or<(With<Void>, With<Ice>)>>;
+
+/// Cells the player can walk onto without pushing anything or losing control
+pub fn walkable<'a>(
+    collision: &'a CollisionMap,
+    hazard_query: &HazardQuery,
+) -> impl Fn(IVec2) -> bool + 'a {
+    let hazards = hazard_query.iter().map(IVec2::from).collect::<Vec<_>>();
+    move |pos| collision.in_bounds(pos) && collision.get(pos).is_none() && !hazards.contains(&pos)
+}
+
+/// Tile under the cursor, if the cursor is inside the window
+pub fn cursor_tile(
+    window_query: &Query<&Window, With<PrimaryWindow>>,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+    grid: &GridConfig,
+) -> Option<IVec2> {
+    let cursor = window_query.get_single().ok()?.cursor_position()?;
+    let (camera, camera_transform) = camera_query.get_single().ok()?;
+    let world = camera.viewport_to_world_2d(camera_transform, cursor)?;
+    Some(grid.to_tile(world))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn click_to_move(
+    mouse_buttons: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    player_query: Query<&Pos, With<Player>>,
+    hazard_query: HazardQuery,
+    collision: Res<CollisionMap>,
+    mut auto_walk: ResMut<AutoWalk>,
+    grid: Res<GridConfig>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(target) = cursor_tile(&window_query, &camera_query, &grid) else {
+        return;
+    };
+    let Ok(player_pos) = player_query.get_single() else {
+        return;
+    };
+
+    match find_path(
+        IVec2::from(player_pos),
+        target,
+        walkable(&collision, &hazard_query),
+    ) {
+        Some(path) => auto_walk.start(path),
+        None => log::debug!("No path to {target}"),
+    }
+}
+
+fn cancel_auto_walk(
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut auto_walk: ResMut<AutoWalk>,
+) {
+    if auto_walk.is_walking()
+        && (keys.get_just_pressed().next().is_some()
+            || gamepad_buttons.get_just_pressed().next().is_some())
+    {
+        auto_walk.clear();
+    }
+}
+
+fn clear_auto_walk(mut auto_walk: ResMut<AutoWalk>) {
+    auto_walk.clear();
+}
@@ -0,0 +1,382 @@
+use std::collections::BTreeSet;
+
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use bevy_ecs_tilemap::tiles::{TileFlip, TileTextureIndex};
+use bevy_pile::grid::Grid;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::util::DIRS;
+
+pub struct AutotilePlugin;
+
+impl Plugin for AutotilePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_asset_loader(AutotileLoader)
+            .init_asset::<Autotile>();
+    }
+}
+
+/// Which of the 8 neighbours of a tile are solid, bit `i` is the neighbour in `DIRS[i]`
+/// so north is 1, north east is 2 and so on clockwise
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Neighbours(u8);
+
+impl Neighbours {
+    /// Diagonals only connect when both cardinals next to them do
+    fn blob(self) -> Neighbours {
+        let mut mask = self.0;
+        for diagonal in [1, 3, 5, 7] {
+            let cardinals = (1 << (diagonal - 1)) | (1 << ((diagonal + 1) % 8));
+            if self.0 & cardinals != cardinals {
+                mask &= !(1 << diagonal);
+            }
+        }
+        Neighbours(mask)
+    }
+}
+
+/// Tiles that walls connect to, computed once per level.
+/// Everything outside of the level counts as solid.
+pub struct NeighbourGrid(Grid<bool>);
+
+impl NeighbourGrid {
+    pub fn new(size: UVec2, solid: Vec<bool>) -> Self {
+        Self(Grid::from_raw(size.as_ivec2(), solid))
+    }
+
+    pub fn get(&self, pos: IVec2) -> Neighbours {
+        let mask = DIRS
+            .iter()
+            .enumerate()
+            .filter(|(_, dir)| self.0.get(pos + **dir).copied().unwrap_or(true))
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+        Neighbours(mask)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WallTile {
+    pub index: TileTextureIndex,
+    pub flip: TileFlip,
+}
+
+/// Wall tile for every combination of neighbours, shipped with the wall tileset
+#[derive(TypePath, TypeUuid, Asset, Clone, Debug)]
+#[uuid = "5d0b8f3e-7a21-4c96-b4e3-91c6f2a8d047"]
+pub struct Autotile {
+    /// Indexed by the neighbour mask
+    tiles: Vec<WallTile>,
+    /// Tile drawn below a wall that has floor under it
+    subwall: u32,
+}
+
+impl Autotile {
+    pub fn wall(&self, neighbours: Neighbours) -> WallTile {
+        self.tiles[neighbours.0 as usize]
+    }
+
+    pub fn subwall(&self) -> TileTextureIndex {
+        TileTextureIndex(self.subwall)
+    }
+
+    fn blob(subwall: u32) -> Self {
+        let masks = (0..=u8::MAX)
+            .map(|mask| Neighbours(mask).blob().0)
+            .collect::<BTreeSet<_>>();
+        let tiles = (0..=u8::MAX)
+            .map(|mask| {
+                let blob = Neighbours(mask).blob().0;
+                WallTile {
+                    index: TileTextureIndex(masks.range(..blob).count() as u32),
+                    flip: TileFlip::default(),
+                }
+            })
+            .collect();
+        Self { tiles, subwall }
+    }
+
+    fn from_rules(subwall: u32, rules: &[AutotileRule]) -> Result<Self, AutotileLoaderError> {
+        let patterns = rules
+            .iter()
+            .map(|rule| Pattern::parse(&rule.neighbours))
+            .collect::<Result<Vec<_>, _>>()?;
+        let tiles = (0..=u8::MAX)
+            .map(|mask| {
+                let (_, rule) = patterns
+                    .iter()
+                    .zip(rules)
+                    .find(|(pattern, _)| pattern.matches(Neighbours(mask)))
+                    .ok_or_else(|| AutotileLoaderError::Unmatched(Pattern::format(mask)))?;
+                Ok(WallTile {
+                    index: TileTextureIndex(rule.index),
+                    flip: TileFlip {
+                        x: rule.flip.x,
+                        y: rule.flip.y,
+                        d: rule.flip.d,
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { tiles, subwall })
+    }
+}
+
+/// Neighbours a rule applies to, one character per neighbour in the order of `DIRS`
+/// `#` is solid, `.` is open and `?` is either
+struct Pattern {
+    solid: u8,
+    care: u8,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self, AutotileLoaderError> {
+        let invalid = || AutotileLoaderError::InvalidPattern(pattern.to_string());
+        if pattern.chars().count() != DIRS.len() {
+            return Err(invalid());
+        }
+        let mut parsed = Pattern { solid: 0, care: 0 };
+        for (i, c) in pattern.chars().enumerate() {
+            match c {
+                '#' => {
+                    parsed.solid |= 1 << i;
+                    parsed.care |= 1 << i;
+                }
+                '.' => parsed.care |= 1 << i,
+                '?' => {}
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn format(mask: u8) -> String {
+        (0..DIRS.len())
+            .map(|i| if mask & 1 << i != 0 { '#' } else { '.' })
+            .collect()
+    }
+
+    fn matches(&self, neighbours: Neighbours) -> bool {
+        neighbours.0 & self.care == self.solid
+    }
+}
+
+#[derive(Deserialize)]
+enum AutotileLayout {
+    /// 47 tile blob tileset without flipping. Diagonals only count when both cardinals
+    /// next to them are solid, the tiles are ordered by the resulting neighbour mask
+    /// from 0 to 255, see `assets/frost/wall.png`
+    Blob { subwall: u32 },
+    /// The first rule that matches the neighbours picks the tile
+    Rules {
+        subwall: u32,
+        rules: Vec<AutotileRule>,
+    },
+}
+
+#[derive(Deserialize)]
+struct AutotileRule {
+    neighbours: String,
+    index: u32,
+    #[serde(default)]
+    flip: Flip,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Flip {
+    x: bool,
+    y: bool,
+    /// Swaps the axes, applied before the other two
+    d: bool,
+}
+
+impl TryFrom<AutotileLayout> for Autotile {
+    type Error = AutotileLoaderError;
+
+    fn try_from(value: AutotileLayout) -> Result<Self, Self::Error> {
+        match value {
+            AutotileLayout::Blob { subwall } => Ok(Autotile::blob(subwall)),
+            AutotileLayout::Rules { subwall, rules } => Autotile::from_rules(subwall, &rules),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AutotileLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AutotileLoaderError {
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the ron: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+    #[error("Invalid neighbours {0:?}, expected 8 of '#', '.' or '?'")]
+    InvalidPattern(String),
+    #[error("No rule matches the neighbours {0:?}")]
+    Unmatched(String),
+}
+
+impl AssetLoader for AutotileLoader {
+    type Asset = Autotile;
+    type Settings = ();
+    type Error = AutotileLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, std::result::Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Autotile::try_from(ron::de::from_bytes::<AutotileLayout>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["autotile"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(ron: &str) -> Autotile {
+        let layout = ron::de::from_str::<AutotileLayout>(ron).expect("Layout should parse");
+        Autotile::try_from(layout).expect("Layout should cover every mask")
+    }
+
+    /// The hand written mapping the classic layout was drawn for
+    fn classic_reference(solid: Neighbours) -> (u32, TileFlip) {
+        let open = |i: usize| solid.0 & 1 << i == 0;
+        let [n, ne, e, se, s, sw, w, nw] = [0, 1, 2, 3, 4, 5, 6, 7].map(open);
+
+        let diag_c: usize = [ne, se, sw, nw].iter().map(|x| *x as usize).sum();
+        let card_c: usize = [n, e, s, w].iter().map(|x| *x as usize).sum();
+
+        let flip = TileFlip {
+            x: e,
+            y: s,
+            d: e || w,
+        };
+        let flip_inv = TileFlip {
+            x: e,
+            y: s,
+            d: !e || !w,
+        };
+        let two_diag = TileFlip {
+            x: ne,
+            y: sw,
+            d: (nw && sw) || (ne && se),
+        };
+        let zero_flip_diag = TileFlip {
+            x: ne || se,
+            y: sw || se,
+            ..default()
+        };
+        let three_diag_flip = TileFlip {
+            x: e || n && se || s && ne,
+            y: s || e && sw || w && se,
+            d: e || w,
+        };
+        match card_c {
+            0 => match diag_c {
+                0 => (8, TileFlip::default()),
+                1 => (9, zero_flip_diag),
+                2 if (nw && se) || (ne && sw) => (11, two_diag),
+                2 => (10, two_diag),
+                3 => (12, zero_flip_diag),
+                _ => (13, TileFlip::default()),
+            },
+            1 if diag_c == 4 => (2, flip),
+            1 if diag_c == 3 => (1, three_diag_flip),
+            1 => (0, flip),
+            2 if (n && s) || (w && e) => (5, flip_inv),
+            2 if (n && w && se) || (n && e && sw) || (s && w && ne) || (s && e && nw) => (4, flip),
+            2 => (3, flip),
+            3 => (6, flip_inv),
+            _ => (7, TileFlip::default()),
+        }
+    }
+
+    #[test]
+    fn classic_matches_reference() {
+        let classic = load(include_str!("../../assets/autotile/classic.autotile"));
+        for mask in 0..=u8::MAX {
+            let (index, flip) = classic_reference(Neighbours(mask));
+            let tile = classic.wall(Neighbours(mask));
+            assert_eq!(
+                (tile.index.0, tile.flip),
+                (index, flip),
+                "Neighbours {}",
+                Pattern::format(mask)
+            );
+        }
+        assert_eq!(classic.subwall().0, 14);
+    }
+
+    #[test]
+    fn blob_has_47_tiles() {
+        let blob = load(include_str!("../../assets/autotile/blob.autotile"));
+        let indices = (0..=u8::MAX)
+            .map(|mask| blob.wall(Neighbours(mask)).index.0)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(indices, (0..47).collect());
+        assert_eq!(blob.wall(Neighbours(0)).index.0, 0);
+        assert_eq!(blob.wall(Neighbours(u8::MAX)).index.0, 46);
+    }
+
+    #[test]
+    fn blob_ignores_unconnected_diagonals() {
+        let blob = Autotile::blob(47);
+        // North east without north and east is the same as no neighbours
+        assert_eq!(blob.wall(Neighbours(0b10)).index.0, 0);
+        // North and east with and without the corner between them differ
+        let corner = blob.wall(Neighbours(0b111)).index.0;
+        let open_corner = blob.wall(Neighbours(0b101)).index.0;
+        assert_ne!(corner, open_corner);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = load(
+            r##"Rules(subwall: 0, rules: [
+                (neighbours: "#???????", index: 1, flip: (x: true)),
+                (neighbours: "????????", index: 2),
+            ])"##,
+        );
+        let north = rules.wall(Neighbours(1));
+        assert_eq!((north.index.0, north.flip.x), (1, true));
+        assert_eq!(rules.wall(Neighbours(0b10)).index.0, 2);
+    }
+
+    #[test]
+    fn rules_must_cover_every_mask() {
+        let layout = ron::de::from_str::<AutotileLayout>(
+            r##"Rules(subwall: 0, rules: [(neighbours: "#???????", index: 1)])"##,
+        )
+        .unwrap();
+        assert!(matches!(
+            Autotile::try_from(layout),
+            Err(AutotileLoaderError::Unmatched(_))
+        ));
+    }
+
+    #[test]
+    fn outside_of_the_level_is_solid() {
+        // Single open tile
+        let grid = NeighbourGrid::new(UVec2::ONE, vec![false]);
+        assert_eq!(grid.get(IVec2::ZERO), Neighbours(u8::MAX));
+        // Two open tiles next to each other
+        let grid = NeighbourGrid::new(UVec2::new(2, 1), vec![false, false]);
+        assert_eq!(grid.get(IVec2::ZERO), Neighbours(!(1 << 2)));
+    }
+}
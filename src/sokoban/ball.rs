@@ -1,6 +1,11 @@
 use bevy::{ecs::system::Command, prelude::*};
 
-use super::{history::History, AssetsCollection, DynamicBundle, Pos};
+use super::{
+    animation::{BallPose, SpriteAnimation},
+    history::History,
+    theme::ActiveTheme,
+    DynamicBundle, Pos,
+};
 
 #[derive(Component, Clone)]
 pub struct Ball;
@@ -21,7 +26,9 @@ impl SpawnBall {
 
 impl Command for SpawnBall {
     fn apply(self, world: &mut World) {
-        let texture = world.resource::<AssetsCollection>().ball.clone();
+        let theme = world.resource::<ActiveTheme>();
+        let texture_atlas = theme.ball_sheet.clone();
+        let animations = theme.ball_animations.clone();
 
         world
             .entity_mut(self.tilemap_entity)
@@ -32,11 +39,14 @@ impl Command for SpawnBall {
                     self.pos,
                     History::<Pos>::default(),
                     DynamicBundle::default(),
-                    SpriteBundle {
-                        texture,
+                    SpriteSheetBundle {
+                        texture_atlas,
                         transform: Transform::from_translation(2. * Vec3::Z),
                         ..default()
                     },
+                    SpriteAnimation::default(),
+                    animations,
+                    BallPose::default(),
                 ));
             });
     }
@@ -0,0 +1,371 @@
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowResized},
+};
+use leafwing_input_manager::prelude::ActionState;
+
+use super::{
+    grid::GridConfig,
+    level::{LevelCollection, Levels},
+    level_select::CurrentLevel,
+    player::Player,
+    settings::DisplaySettings,
+    GameState, Pos, SokobanActions, LEVEL_STATES,
+};
+
+/// Free space around the level in tiles
+const MARGIN_TILES: f32 = 1.;
+/// Smallest zoom the level is fitted at, levels that don't fit at it are scrolled
+const MIN_FIT_ZOOM: u32 = 2;
+/// Range the player can zoom in
+const MIN_ZOOM: u32 = 1;
+const MAX_ZOOM: u32 = 12;
+/// How quickly the camera catches up with the player, higher is faster
+const FOLLOW_SPEED: f32 = 6.;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraFit>()
+            .register_type::<CameraFit>()
+            .add_systems(
+                Update,
+                (
+                    // The level stays fitted while the window is resized
+                    refit_camera_on_resize
+                        .run_if(on_event::<WindowResized>())
+                        .run_if(|state: Res<State<GameState>>| LEVEL_STATES.contains(state.get())),
+                    zoom_camera.run_if(in_state(GameState::Play)),
+                    pan_camera.run_if(in_state(GameState::Play)),
+                    follow_player.run_if(in_state(GameState::Play)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// How the camera shows the current level
+#[derive(Resource, Reflect, Default, Debug)]
+#[reflect(Resource)]
+pub struct CameraFit {
+    /// Zoom that fits the level, the view returns to it when it is reset
+    pub fit_zoom: u32,
+    /// Screen pixels per world pixel
+    pub zoom: u32,
+    /// The level doesn't fit in the view, so the camera scrolls with the player
+    pub follows: bool,
+    /// The view was dragged away, following resumes once the player moves
+    pub panned: bool,
+    /// Where the camera looks at before snapping to the pixel grid
+    position: Vec2,
+}
+
+/// Area of the level in world units, including the margin
+fn level_bounds(size: UVec2, grid: &GridConfig) -> Rect {
+    let tile_size = grid.tile_size;
+    let margin = MARGIN_TILES * tile_size;
+    // Tiles are centered on their position
+    let min = Vec2::splat(-tile_size / 2. - margin);
+    let max = size.as_vec2() * tile_size - tile_size / 2. + margin;
+    Rect::from_corners(min, max)
+}
+
+/// Largest integer zoom that shows all of `bounds` in `physical_size`
+fn fitting_zoom(bounds: Rect, physical_size: Vec2) -> u32 {
+    let fit = physical_size / bounds.size();
+    fit.x.min(fit.y).floor() as u32
+}
+
+/// Keeps the view inside `bounds`, axes where the view is larger are centered
+fn clamp_to_bounds(position: Vec2, view: Vec2, bounds: Rect) -> Vec2 {
+    let clamp_axis = |pos: f32, view: f32, min: f32, max: f32| {
+        if view >= max - min {
+            (min + max) / 2.
+        } else {
+            pos.clamp(min + view / 2., max - view / 2.)
+        }
+    };
+    Vec2::new(
+        clamp_axis(position.x, view.x, bounds.min.x, bounds.max.x),
+        clamp_axis(position.y, view.y, bounds.min.y, bounds.max.y),
+    )
+}
+
+/// Rounds `position` to the screen pixel grid so sprites don't shimmer
+fn snap_to_pixels(position: Vec2, zoom: u32) -> Vec2 {
+    (position * zoom as f32).round() / zoom as f32
+}
+
+fn current_level_size(
+    current_level: &CurrentLevel,
+    level_collection: &LevelCollection,
+    levels: &Assets<Levels>,
+) -> Option<UVec2> {
+    levels
+        .get(&level_collection.levels)
+        .and_then(|levels| levels.get(**current_level))
+        .map(|level| level.size)
+}
+
+fn view_size(window: &Window, zoom: u32) -> Vec2 {
+    Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    ) / zoom as f32
+}
+
+/// Picks the zoom for the current level and window and moves the camera there.
+/// `zoom_offset` is how many steps the view is zoomed in from the fitting zoom.
+fn fit(
+    fit: &mut CameraFit,
+    zoom_offset: i32,
+    window: &Window,
+    bounds: Rect,
+    player: Option<Vec2>,
+    transform: &mut Transform,
+    projection: &mut OrthographicProjection,
+) {
+    let physical_size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    fit.fit_zoom = fitting_zoom(bounds, physical_size).clamp(MIN_FIT_ZOOM, MAX_ZOOM);
+    fit.zoom = (fit.fit_zoom as i32 + zoom_offset).clamp(MIN_ZOOM as i32, MAX_ZOOM as i32) as u32;
+    apply_zoom(fit, window, bounds, player, transform, projection);
+}
+
+/// Moves the camera to show the level at the current zoom
+fn apply_zoom(
+    fit: &mut CameraFit,
+    window: &Window,
+    bounds: Rect,
+    player: Option<Vec2>,
+    transform: &mut Transform,
+    projection: &mut OrthographicProjection,
+) {
+    let view = view_size(window, fit.zoom);
+    fit.follows = view.x < bounds.width() || view.y < bounds.height();
+    fit.position = match player {
+        Some(_) if fit.follows && fit.panned => clamp_to_bounds(fit.position, view, bounds),
+        Some(player) if fit.follows => clamp_to_bounds(player, view, bounds),
+        _ => bounds.center(),
+    };
+
+    // The projection works in logical pixels
+    projection.scale = window.scale_factor() as f32 / fit.zoom as f32;
+    let position = snap_to_pixels(fit.position, fit.zoom);
+    transform.translation = position.extend(transform.translation.z);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn fit_camera_to_level(
+    mut camera_fit: ResMut<CameraFit>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    player_q: Query<&Pos, With<Player>>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    grid: Res<GridConfig>,
+) {
+    let (Ok((mut transform, mut projection)), Ok(window)) =
+        (camera_q.get_single_mut(), window_q.get_single())
+    else {
+        return;
+    };
+    let Some(size) = current_level_size(&current_level, &level_collection, &levels) else {
+        return;
+    };
+    // The transform of a freshly spawned player isn't set yet
+    let player = player_q.get_single().ok().map(|pos| grid.to_world(pos));
+    camera_fit.panned = false;
+    fit(
+        &mut camera_fit,
+        0,
+        window,
+        level_bounds(size, &grid),
+        player,
+        &mut transform,
+        &mut projection,
+    );
+}
+
+/// Fits the level to the new window size, keeping how far the player zoomed in
+#[allow(clippy::too_many_arguments)]
+fn refit_camera_on_resize(
+    mut camera_fit: ResMut<CameraFit>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    player_q: Query<&Transform, (With<Player>, Without<Camera>)>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    grid: Res<GridConfig>,
+) {
+    let (Ok((mut transform, mut projection)), Ok(window)) =
+        (camera_q.get_single_mut(), window_q.get_single())
+    else {
+        return;
+    };
+    let Some(size) = current_level_size(&current_level, &level_collection, &levels) else {
+        return;
+    };
+    let player = player_q
+        .get_single()
+        .ok()
+        .map(|player| player.translation.truncate());
+    let zoom_offset = camera_fit.zoom as i32 - camera_fit.fit_zoom as i32;
+    fit(
+        &mut camera_fit,
+        zoom_offset,
+        window,
+        level_bounds(size, &grid),
+        player,
+        &mut transform,
+        &mut projection,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn zoom_camera(
+    actions: Query<&ActionState<SokobanActions>>,
+    mut camera_fit: ResMut<CameraFit>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    player_q: Query<&Transform, (With<Player>, Without<Camera>)>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    grid: Res<GridConfig>,
+) {
+    let Ok(actions) = actions.get_single() else {
+        return;
+    };
+    // Zoom in whole steps so every world pixel stays the same size on screen
+    let zoom = if actions.just_pressed(SokobanActions::ResetView) {
+        // Following the player again brings a dragged view back
+        camera_fit.panned = false;
+        camera_fit.fit_zoom
+    } else if actions.just_pressed(SokobanActions::ZoomIn) {
+        (camera_fit.zoom + 1).min(MAX_ZOOM)
+    } else if actions.just_pressed(SokobanActions::ZoomOut) {
+        camera_fit.zoom.saturating_sub(1).max(MIN_ZOOM)
+    } else {
+        return;
+    };
+    if zoom == camera_fit.zoom {
+        return;
+    }
+    let (Ok((mut transform, mut projection)), Ok(window)) =
+        (camera_q.get_single_mut(), window_q.get_single())
+    else {
+        return;
+    };
+    let Some(size) = current_level_size(&current_level, &level_collection, &levels) else {
+        return;
+    };
+    let player = player_q
+        .get_single()
+        .ok()
+        .map(|player| player.translation.truncate());
+    camera_fit.zoom = zoom;
+    apply_zoom(
+        &mut camera_fit,
+        window,
+        level_bounds(size, &grid),
+        player,
+        &mut transform,
+        &mut projection,
+    );
+}
+
+/// Drags the view of levels that don't fit around with the mouse
+#[allow(clippy::too_many_arguments)]
+fn pan_camera(
+    actions: Query<&ActionState<SokobanActions>>,
+    mut camera_fit: ResMut<CameraFit>,
+    mut camera_q: Query<&mut Transform, With<Camera>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    mut last_cursor: Local<Option<Vec2>>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    grid: Res<GridConfig>,
+) {
+    let (Ok(actions), Ok(window)) = (actions.get_single(), window_q.get_single()) else {
+        return;
+    };
+    let cursor = window
+        .cursor_position()
+        .filter(|_| actions.pressed(SokobanActions::Pan));
+    let (Some(cursor), Some(last)) = (cursor, std::mem::replace(&mut *last_cursor, cursor)) else {
+        return;
+    };
+    if cursor == last || !camera_fit.follows {
+        return;
+    }
+    let Ok(mut transform) = camera_q.get_single_mut() else {
+        return;
+    };
+    let Some(size) = current_level_size(&current_level, &level_collection, &levels) else {
+        return;
+    };
+    // The cursor moves in logical pixels and down is positive
+    let delta = (cursor - last) * window.scale_factor() as f32 / camera_fit.zoom as f32;
+    camera_fit.panned = true;
+    camera_fit.position = clamp_to_bounds(
+        camera_fit.position + Vec2::new(-delta.x, delta.y),
+        view_size(window, camera_fit.zoom),
+        level_bounds(size, &grid),
+    );
+    let position = snap_to_pixels(camera_fit.position, camera_fit.zoom);
+    transform.translation = position.extend(transform.translation.z);
+}
+
+/// Scrolls levels that don't fit in the view along with the player
+#[allow(clippy::too_many_arguments)]
+fn follow_player(
+    mut camera_fit: ResMut<CameraFit>,
+    mut camera_q: Query<&mut Transform, With<Camera>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    player_q: Query<&Transform, (With<Player>, Without<Camera>)>,
+    moved_q: Query<(), (With<Player>, Changed<Pos>)>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    display: Res<DisplaySettings>,
+    grid: Res<GridConfig>,
+    time: Res<Time>,
+) {
+    if !moved_q.is_empty() {
+        camera_fit.panned = false;
+    }
+    if !camera_fit.follows || camera_fit.panned {
+        return;
+    }
+    let (Ok(mut transform), Ok(window), Ok(player)) = (
+        camera_q.get_single_mut(),
+        window_q.get_single(),
+        player_q.get_single(),
+    ) else {
+        return;
+    };
+    let Some(size) = current_level_size(&current_level, &level_collection, &levels) else {
+        return;
+    };
+    let target = clamp_to_bounds(
+        player.translation.truncate(),
+        view_size(window, camera_fit.zoom),
+        level_bounds(size, &grid),
+    );
+    camera_fit.position = if display.reduced_motion {
+        target
+    } else {
+        // Frame rate independent exponential smoothing
+        let t = 1. - (-FOLLOW_SPEED * time.delta_seconds()).exp();
+        camera_fit.position.lerp(target, t)
+    };
+    let position = snap_to_pixels(camera_fit.position, camera_fit.zoom);
+    transform.translation = position.extend(transform.translation.z);
+}
@@ -2,8 +2,10 @@ use bevy::{log, prelude::*};
 use bevy_pile::grid::Grid;
 
 use super::{
+    inventory::Lock,
     level::{LevelCollection, Levels},
     level_select::CurrentLevel,
+    momentum::transfer_momentum,
     Dir, GameState, Pos, SokobanBlock,
 };
 
@@ -19,6 +21,13 @@ impl Plugin for CollisionPlugin {
                 },
                 init_collision_map,
             )
+            // Several fixed steps can run in one frame, so keep the map current between them too
+            .add_systems(
+                FixedUpdate,
+                sync_collision_map
+                    .before(transfer_momentum)
+                    .run_if(in_state(GameState::Play)),
+            )
             .add_systems(
                 PostUpdate,
                 sync_collision_map.run_if(in_state(GameState::Play)),
@@ -45,33 +54,43 @@ pub fn init_collision_map(
     current_level: Res<CurrentLevel>,
     levels: Res<Assets<Levels>>,
     levels_collection: Res<LevelCollection>,
-    sokoban_entities: Query<(Entity, &Pos, &SokobanBlock)>,
+    sokoban_entities: Query<(Entity, &Pos, &SokobanBlock, Option<&Lock>)>,
 ) {
     // TODO Make some system param for this
-    let size = levels
+    let Some(size) = levels
         .get(&levels_collection.levels)
-        .expect("Level assets should be loaded")
-        .get(**current_level)
-        .expect("Current level will always be a valid level index")
-        .size;
+        .and_then(|levels| levels.get(**current_level))
+        .map(|level| level.size)
+    else {
+        // `spawn_level` already left for the level select
+        cmds.insert_resource(CollisionMap::default());
+        return;
+    };
     log::debug!("Initialized collision map");
-    let mut map = Grid::new(IVec2::new(size.x as i32, size.y as i32), None);
-    for (entity, pos, block) in sokoban_entities.iter() {
+    let mut collision = CollisionMap::new(size);
+    for (entity, pos, block, lock) in sokoban_entities.iter() {
+        if lock.is_some_and(|lock| lock.open) {
+            continue;
+        }
         let pos = IVec2::from(pos);
-        map.set(pos, Some((entity, *block)));
+        collision.map.set(pos, Some((entity, *block)));
     }
-    cmds.insert_resource(CollisionMap { map });
+    cmds.insert_resource(collision);
 }
 
 // TODO dont rebuild but instead only change moved entities
-fn sync_collision_map(
+pub fn sync_collision_map(
     mut collision: ResMut<CollisionMap>,
-    sokoban_entities: Query<(Entity, &Pos, &SokobanBlock)>,
+    sokoban_entities: Query<(Entity, &Pos, &SokobanBlock, Option<&Lock>)>,
 ) {
     collision.map.iter_mut().for_each(|(_, elem)| {
         elem.take();
     });
-    for (entity, pos, block) in sokoban_entities.iter() {
+    for (entity, pos, block, lock) in sokoban_entities.iter() {
+        // Opened locks stay around for undo but no longer block
+        if lock.is_some_and(|lock| lock.open) {
+            continue;
+        }
         collision.map.set(IVec2::from(pos), Some((entity, *block)));
     }
 }
@@ -83,7 +102,29 @@ pub enum CollisionResult {
 }
 
 impl CollisionMap {
-    pub fn push_collision(&self, pusher_pos: IVec2, direction: Dir) -> CollisionResult {
+    /// An empty map of a level of `size`
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            map: Grid::new(IVec2::new(size.x as i32, size.y as i32), None),
+        }
+    }
+
+    pub fn get(&self, pos: IVec2) -> Option<(Entity, SokobanBlock)> {
+        self.map.get(pos).copied().flatten()
+    }
+
+    pub fn in_bounds(&self, pos: IVec2) -> bool {
+        self.map.get(pos).is_some()
+    }
+
+    /// Entities that move when `pusher_pos` pushes in `direction`, starting with the pusher.
+    /// Crates are pushed one at a time, anything in front of a crate blocks it.
+    pub fn push_collision(
+        &self,
+        pusher_pos: IVec2,
+        direction: Dir,
+        is_crate: impl Fn(Entity) -> bool,
+    ) -> CollisionResult {
         let Some(Some((pusher, _))) = self.map.get(pusher_pos) else {
             return CollisionResult::OutOfBounds;
         };
@@ -100,6 +141,9 @@ impl CollisionMap {
                         return CollisionResult::Wall;
                     }
                     SokobanBlock::Dynamic => {
+                        if is_crate(*pusher) {
+                            return CollisionResult::Wall;
+                        }
                         moving_entities.push(*pusher);
                         pusher = pushed;
                         current_pos = dest;
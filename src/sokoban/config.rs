@@ -0,0 +1,73 @@
+#[cfg(not(target_family = "wasm"))]
+use std::path::PathBuf;
+
+use bevy::log;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// Directory the config files are stored in, relative to the working directory
+#[cfg(not(target_family = "wasm"))]
+const CONFIG_DIR: &str = "config";
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not access the config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the ron: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+    #[error("Could not serialize the config: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("Config files are not supported on this platform")]
+    Unsupported,
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn config_path(file: &str) -> PathBuf {
+    PathBuf::from(CONFIG_DIR).join(file)
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn load<T: DeserializeOwned>(file: &str) -> Result<T, ConfigError> {
+    let contents = std::fs::read_to_string(config_path(file))?;
+    Ok(ron::from_str(&contents)?)
+}
+
+#[cfg(target_family = "wasm")]
+pub fn load<T: DeserializeOwned>(_file: &str) -> Result<T, ConfigError> {
+    Err(ConfigError::Unsupported)
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn save<T: Serialize>(file: &str, value: &T) -> Result<(), ConfigError> {
+    let contents = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    std::fs::create_dir_all(CONFIG_DIR)?;
+    std::fs::write(config_path(file), contents)?;
+    Ok(())
+}
+
+#[cfg(target_family = "wasm")]
+pub fn save<T: Serialize>(_file: &str, _value: &T) -> Result<(), ConfigError> {
+    Err(ConfigError::Unsupported)
+}
+
+/// Loads a config file, falling back to the default if it is missing or invalid
+pub fn load_or_default<T: DeserializeOwned + Default>(file: &str) -> T {
+    match load(file) {
+        Ok(value) => value,
+        Err(ConfigError::Unsupported) => T::default(),
+        Err(ConfigError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => T::default(),
+        Err(err) => {
+            log::warn!("Using default {file}: {err}");
+            T::default()
+        }
+    }
+}
+
+/// Saves a config file, logging instead of failing since the game can go on without it
+pub fn save_or_warn<T: Serialize>(file: &str, value: &T) {
+    match save(file, value) {
+        Ok(()) | Err(ConfigError::Unsupported) => {}
+        Err(err) => log::warn!("Could not save {file}: {err}"),
+    }
+}
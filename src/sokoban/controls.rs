@@ -0,0 +1,736 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cleanup::DependOnState, config, gamepad::button_name, player::PlayerActions,
+    theme::ActiveTheme, ui::NineSliceButtonText, GameState, SokobanActions,
+};
+
+const CONTROLS_FILE: &str = "controls.ron";
+const DEFAULT_STICK_DEADZONE: f32 = 0.5;
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Controls::load())
+            .add_event::<ControlsInteraction>()
+            .init_resource::<RebindState>()
+            .init_resource::<SelectedBinding>()
+            .add_systems(OnEnter(GameState::Controls), spawn_controls_menu)
+            .add_systems(OnExit(GameState::Controls), save_controls)
+            .add_systems(
+                Update,
+                apply_controls.run_if(resource_changed::<Controls>()),
+            )
+            .add_systems(
+                Update,
+                (
+                    capture_binding,
+                    handle_buttons,
+                    ui_navigation,
+                    handle_interaction,
+                    button_labels,
+                    render_selected_border,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            );
+    }
+}
+
+/// Inputs bound to a single action
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub keys: Vec<KeyCode>,
+    pub gamepad: Option<GamepadButtonType>,
+}
+
+impl Binding {
+    fn new(keys: &[KeyCode], gamepad: Option<GamepadButtonType>) -> Self {
+        Self {
+            keys: keys.to_vec(),
+            gamepad,
+        }
+    }
+
+    fn contains(&self, input: BindingInput) -> bool {
+        match input {
+            BindingInput::Key(key) => self.keys.contains(&key),
+            BindingInput::Gamepad(button) => self.gamepad == Some(button),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BindingInput {
+    Key(KeyCode),
+    Gamepad(GamepadButtonType),
+}
+
+impl std::fmt::Display for BindingInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindingInput::Key(key) => write!(f, "{key:?}"),
+            BindingInput::Gamepad(button) => write!(f, "{}", button_name(*button)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BoundAction {
+    Player(PlayerActions),
+    Sokoban(SokobanActions),
+}
+
+const ALL_ACTIONS: [BoundAction; 18] = [
+    BoundAction::Player(PlayerActions::Up),
+    BoundAction::Player(PlayerActions::Right),
+    BoundAction::Player(PlayerActions::Down),
+    BoundAction::Player(PlayerActions::Left),
+    BoundAction::Sokoban(SokobanActions::Undo),
+    BoundAction::Sokoban(SokobanActions::Reset),
+    BoundAction::Sokoban(SokobanActions::Escape),
+    BoundAction::Sokoban(SokobanActions::FastForward),
+    BoundAction::Sokoban(SokobanActions::ChangeSpeed),
+    BoundAction::Sokoban(SokobanActions::ToggleInstant),
+    BoundAction::Sokoban(SokobanActions::ZoomIn),
+    BoundAction::Sokoban(SokobanActions::ZoomOut),
+    BoundAction::Sokoban(SokobanActions::ResetView),
+    BoundAction::Sokoban(SokobanActions::UiNavUp),
+    BoundAction::Sokoban(SokobanActions::UiNavRight),
+    BoundAction::Sokoban(SokobanActions::UiNavDown),
+    BoundAction::Sokoban(SokobanActions::UiNavLeft),
+    BoundAction::Sokoban(SokobanActions::UiNavSelect),
+];
+
+impl BoundAction {
+    /// Actions only conflict if they can be triggered in the same place.
+    /// Moving and menu navigation share keys on purpose.
+    fn in_gameplay(&self) -> bool {
+        !self.in_menu() || matches!(self, BoundAction::Sokoban(SokobanActions::Escape))
+    }
+
+    fn in_menu(&self) -> bool {
+        use SokobanActions::*;
+        matches!(
+            self,
+            BoundAction::Sokoban(
+                UiNavUp | UiNavRight | UiNavDown | UiNavLeft | UiNavSelect | Escape
+            )
+        )
+    }
+
+    fn conflicts_with(&self, other: &BoundAction) -> bool {
+        self != other
+            && ((self.in_gameplay() && other.in_gameplay()) || (self.in_menu() && other.in_menu()))
+    }
+}
+
+impl std::fmt::Display for BoundAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BoundAction::Player(PlayerActions::Up) => "Move Up",
+            BoundAction::Player(PlayerActions::Right) => "Move Right",
+            BoundAction::Player(PlayerActions::Down) => "Move Down",
+            BoundAction::Player(PlayerActions::Left) => "Move Left",
+            BoundAction::Sokoban(SokobanActions::Undo) => "Undo",
+            BoundAction::Sokoban(SokobanActions::Reset) => "Reset",
+            BoundAction::Sokoban(SokobanActions::Escape) => "Pause / Back",
+            BoundAction::Sokoban(SokobanActions::FastForward) => "Fast Forward",
+            BoundAction::Sokoban(SokobanActions::ChangeSpeed) => "Change Speed",
+            BoundAction::Sokoban(SokobanActions::ToggleInstant) => "Instant Mode",
+            BoundAction::Sokoban(SokobanActions::ZoomIn) => "Zoom In",
+            BoundAction::Sokoban(SokobanActions::ZoomOut) => "Zoom Out",
+            BoundAction::Sokoban(SokobanActions::ResetView) => "Reset View",
+            BoundAction::Sokoban(SokobanActions::Pan) => "Pan View",
+            BoundAction::Sokoban(SokobanActions::UiNavUp) => "Menu Up",
+            BoundAction::Sokoban(SokobanActions::UiNavRight) => "Menu Right",
+            BoundAction::Sokoban(SokobanActions::UiNavDown) => "Menu Down",
+            BoundAction::Sokoban(SokobanActions::UiNavLeft) => "Menu Left",
+            BoundAction::Sokoban(SokobanActions::UiNavSelect) => "Menu Select",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Bindings for every rebindable action, persisted to the config directory
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct Controls {
+    pub player: HashMap<PlayerActions, Binding>,
+    pub sokoban: HashMap<SokobanActions, Binding>,
+    /// How far the left stick has to be tilted to count as a direction
+    #[serde(default = "default_stick_deadzone")]
+    pub stick_deadzone: f32,
+}
+
+fn default_stick_deadzone() -> f32 {
+    DEFAULT_STICK_DEADZONE
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        Self::wasd()
+    }
+}
+
+impl Controls {
+    pub fn wasd() -> Self {
+        use GamepadButtonType::*;
+        use SokobanActions::*;
+
+        Self {
+            player: HashMap::from([
+                (PlayerActions::Up, Binding::new(&[KeyCode::W], Some(DPadUp))),
+                (
+                    PlayerActions::Right,
+                    Binding::new(&[KeyCode::D], Some(DPadRight)),
+                ),
+                (
+                    PlayerActions::Down,
+                    Binding::new(&[KeyCode::S], Some(DPadDown)),
+                ),
+                (
+                    PlayerActions::Left,
+                    Binding::new(&[KeyCode::A], Some(DPadLeft)),
+                ),
+            ]),
+            sokoban: HashMap::from([
+                (Undo, Binding::new(&[KeyCode::E], Some(West))),
+                (Reset, Binding::new(&[KeyCode::R], Some(North))),
+                (
+                    Escape,
+                    Binding::new(&[KeyCode::Escape, KeyCode::Q], Some(Start)),
+                ),
+                (
+                    FastForward,
+                    Binding::new(&[KeyCode::Space], Some(RightTrigger)),
+                ),
+                (ChangeSpeed, Binding::new(&[KeyCode::Tab], Some(Select))),
+                (ToggleInstant, Binding::new(&[KeyCode::T], None)),
+                (
+                    ZoomIn,
+                    Binding::new(&[KeyCode::Equals], Some(RightTrigger2)),
+                ),
+                (ZoomOut, Binding::new(&[KeyCode::Minus], Some(LeftTrigger2))),
+                (ResetView, Binding::new(&[KeyCode::Key0], Some(RightThumb))),
+                (UiNavUp, Binding::new(&[KeyCode::W], Some(DPadUp))),
+                (UiNavRight, Binding::new(&[KeyCode::D], Some(DPadRight))),
+                (UiNavDown, Binding::new(&[KeyCode::S], Some(DPadDown))),
+                (UiNavLeft, Binding::new(&[KeyCode::A], Some(DPadLeft))),
+                (UiNavSelect, Binding::new(&[KeyCode::F], Some(South))),
+            ]),
+            stick_deadzone: DEFAULT_STICK_DEADZONE,
+        }
+    }
+
+    pub fn arrows() -> Self {
+        use SokobanActions::*;
+
+        let mut controls = Self::wasd();
+        let keys = [
+            (BoundAction::Player(PlayerActions::Up), KeyCode::Up),
+            (BoundAction::Player(PlayerActions::Right), KeyCode::Right),
+            (BoundAction::Player(PlayerActions::Down), KeyCode::Down),
+            (BoundAction::Player(PlayerActions::Left), KeyCode::Left),
+            (BoundAction::Sokoban(Undo), KeyCode::Z),
+            (BoundAction::Sokoban(Escape), KeyCode::Escape),
+            (BoundAction::Sokoban(UiNavUp), KeyCode::Up),
+            (BoundAction::Sokoban(UiNavRight), KeyCode::Right),
+            (BoundAction::Sokoban(UiNavDown), KeyCode::Down),
+            (BoundAction::Sokoban(UiNavLeft), KeyCode::Left),
+            (BoundAction::Sokoban(UiNavSelect), KeyCode::Return),
+        ];
+        for (action, key) in keys {
+            controls.bind(action, BindingInput::Key(key));
+        }
+        controls
+    }
+
+    /// Loads the saved bindings, actions missing from the file keep their default
+    fn load() -> Self {
+        let mut controls = Self::default();
+        let saved: Controls = config::load_or_default(CONTROLS_FILE);
+        controls.player.extend(saved.player);
+        controls.sokoban.extend(saved.sokoban);
+        controls.stick_deadzone = saved.stick_deadzone.clamp(0.1, 0.9);
+        controls
+    }
+
+    pub fn binding(&self, action: BoundAction) -> Option<&Binding> {
+        match action {
+            BoundAction::Player(action) => self.player.get(&action),
+            BoundAction::Sokoban(action) => self.sokoban.get(&action),
+        }
+    }
+
+    fn binding_mut(&mut self, action: BoundAction) -> &mut Binding {
+        match action {
+            BoundAction::Player(action) => self.player.entry(action).or_default(),
+            BoundAction::Sokoban(action) => self.sokoban.entry(action).or_default(),
+        }
+    }
+
+    /// Returns an action that would share the input with `action`
+    pub fn conflict(&self, action: BoundAction, input: BindingInput) -> Option<BoundAction> {
+        ALL_ACTIONS.into_iter().find(|other| {
+            action.conflicts_with(other)
+                && self
+                    .binding(*other)
+                    .is_some_and(|binding| binding.contains(input))
+        })
+    }
+
+    pub fn bind(&mut self, action: BoundAction, input: BindingInput) {
+        let binding = self.binding_mut(action);
+        match input {
+            BindingInput::Key(key) => binding.keys = vec![key],
+            BindingInput::Gamepad(button) => binding.gamepad = Some(button),
+        }
+    }
+
+    pub fn player_input_map(&self) -> InputMap<PlayerActions> {
+        let mut input_map = input_map(&self.player);
+        self.insert_stick(
+            &mut input_map,
+            [
+                PlayerActions::Up,
+                PlayerActions::Right,
+                PlayerActions::Down,
+                PlayerActions::Left,
+            ],
+        );
+        input_map
+    }
+
+    pub fn sokoban_input_map(&self) -> InputMap<SokobanActions> {
+        let mut input_map = input_map(&self.sokoban);
+        self.insert_stick(
+            &mut input_map,
+            [
+                SokobanActions::UiNavUp,
+                SokobanActions::UiNavRight,
+                SokobanActions::UiNavDown,
+                SokobanActions::UiNavLeft,
+            ],
+        );
+        // The mouse wheel and dragging can't be rebound, like the stick
+        input_map.insert(MouseWheelDirection::Up, SokobanActions::ZoomIn);
+        input_map.insert(MouseWheelDirection::Down, SokobanActions::ZoomOut);
+        input_map.insert(MouseButton::Middle, SokobanActions::Pan);
+        input_map
+    }
+
+    /// The left stick always drives the directional actions, in up, right, down, left order.
+    /// Held directions repeat through the same paths as held keys, e.g. `MovementTimer`.
+    fn insert_stick<A: Actionlike>(
+        &self,
+        input_map: &mut InputMap<A>,
+        [up, right, down, left]: [A; 4],
+    ) {
+        use GamepadAxisType::*;
+        let deadzone = self.stick_deadzone;
+        input_map.insert(SingleAxis::positive_only(LeftStickY, deadzone), up);
+        input_map.insert(SingleAxis::positive_only(LeftStickX, deadzone), right);
+        input_map.insert(SingleAxis::negative_only(LeftStickY, -deadzone), down);
+        input_map.insert(SingleAxis::negative_only(LeftStickX, -deadzone), left);
+    }
+}
+
+fn input_map<A: Actionlike + Copy>(bindings: &HashMap<A, Binding>) -> InputMap<A> {
+    let mut input_map = InputMap::default();
+    for (action, binding) in bindings.iter() {
+        for key in binding.keys.iter() {
+            input_map.insert(*key, *action);
+        }
+        if let Some(button) = binding.gamepad {
+            input_map.insert(button, *action);
+        }
+    }
+    input_map
+}
+
+fn apply_controls(
+    controls: Res<Controls>,
+    mut player_maps: Query<&mut InputMap<PlayerActions>>,
+    mut sokoban_maps: Query<&mut InputMap<SokobanActions>>,
+) {
+    for mut input_map in player_maps.iter_mut() {
+        *input_map = controls.player_input_map();
+    }
+    for mut input_map in sokoban_maps.iter_mut() {
+        *input_map = controls.sokoban_input_map();
+    }
+}
+
+fn save_controls(controls: Res<Controls>) {
+    config::save_or_warn(CONTROLS_FILE, &*controls);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Device {
+    Keyboard,
+    Gamepad,
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum ControlsButton {
+    Bind(BoundAction, Device),
+    WasdDefaults,
+    ArrowDefaults,
+    Back,
+}
+
+const FOOTER: [ControlsButton; 3] = [
+    ControlsButton::WasdDefaults,
+    ControlsButton::ArrowDefaults,
+    ControlsButton::Back,
+];
+
+impl ControlsButton {
+    fn at(row: usize, col: usize) -> Self {
+        match ALL_ACTIONS.get(row) {
+            Some(action) if col == 0 => ControlsButton::Bind(*action, Device::Keyboard),
+            Some(action) => ControlsButton::Bind(*action, Device::Gamepad),
+            None => FOOTER[col.min(FOOTER.len() - 1)],
+        }
+    }
+
+    fn row_len(row: usize) -> usize {
+        if row < ALL_ACTIONS.len() {
+            2
+        } else {
+            FOOTER.len()
+        }
+    }
+
+    fn label(&self, controls: &Controls) -> String {
+        match self {
+            ControlsButton::Bind(action, device) => {
+                let binding = controls.binding(*action).cloned().unwrap_or_default();
+                let label = match device {
+                    Device::Keyboard => binding
+                        .keys
+                        .iter()
+                        .map(|key| BindingInput::Key(*key).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    Device::Gamepad => binding
+                        .gamepad
+                        .map(|button| BindingInput::Gamepad(button).to_string())
+                        .unwrap_or_default(),
+                };
+                if label.is_empty() {
+                    "-".to_string()
+                } else {
+                    label
+                }
+            }
+            _ => String::from(*self),
+        }
+    }
+}
+
+impl From<ControlsButton> for String {
+    fn from(value: ControlsButton) -> Self {
+        match value {
+            // Filled in by `button_labels` once the bindings are known
+            ControlsButton::Bind(..) => "",
+            ControlsButton::WasdDefaults => "WASD Defaults",
+            ControlsButton::ArrowDefaults => "Arrow Defaults",
+            ControlsButton::Back => "Back",
+        }
+        .to_string()
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum RebindState {
+    #[default]
+    Idle,
+    Waiting(BoundAction, Device),
+    /// An input was captured this frame and must not be used for navigation
+    Captured,
+}
+
+#[derive(Resource, Default)]
+struct SelectedBinding {
+    row: usize,
+    col: usize,
+}
+
+#[derive(Event, Deref, DerefMut)]
+struct ControlsInteraction(pub ControlsButton);
+
+#[derive(Component)]
+struct ControlsStatus;
+
+fn spawn_controls_menu(
+    mut cmds: Commands,
+    theme: Res<ActiveTheme>,
+    mut selected: ResMut<SelectedBinding>,
+    mut rebind: ResMut<RebindState>,
+) {
+    *selected = SelectedBinding::default();
+    *rebind = RebindState::Idle;
+
+    let button_texture = theme.button.clone_weak();
+    let button_style = Style {
+        width: Val::Px(130.0),
+        height: Val::Px(26.0),
+        margin: UiRect::all(Val::Px(2.)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        border: UiRect::all(Val::Px(2.)),
+        ..default()
+    };
+    let row_style = Style {
+        flex_direction: FlexDirection::Row,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let text_style = TextStyle {
+        font_size: 20.,
+        color: Color::BLACK,
+        ..default()
+    };
+
+    let root = cmds
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            DependOnState::single(GameState::Controls),
+            Name::new("Controls Menu"),
+        ))
+        .id();
+
+    cmds.entity(root).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section("", text_style.clone()),
+            ControlsStatus,
+        ));
+    });
+
+    for action in ALL_ACTIONS {
+        let row = cmds
+            .spawn(NodeBundle {
+                style: row_style.clone(),
+                ..default()
+            })
+            .id();
+        cmds.entity(root).add_child(row);
+        cmds.entity(row).with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(action.to_string(), text_style.clone()).with_style(
+                    Style {
+                        width: Val::Px(150.),
+                        ..default()
+                    },
+                ),
+            );
+        });
+        for device in [Device::Keyboard, Device::Gamepad] {
+            cmds.add(NineSliceButtonText {
+                button: ControlsButton::Bind(action, device),
+                style: button_style.clone(),
+                texture: button_texture.clone_weak(),
+                parent: row,
+            });
+        }
+    }
+
+    let footer = cmds
+        .spawn(NodeBundle {
+            style: row_style,
+            ..default()
+        })
+        .id();
+    cmds.entity(root).add_child(footer);
+    for button in FOOTER {
+        cmds.add(NineSliceButtonText {
+            button,
+            style: button_style.clone(),
+            texture: button_texture.clone_weak(),
+            parent: footer,
+        });
+    }
+}
+
+fn capture_binding(
+    mut rebind: ResMut<RebindState>,
+    mut controls: ResMut<Controls>,
+    mut status_query: Query<&mut Text, With<ControlsStatus>>,
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    let RebindState::Waiting(action, device) = *rebind else {
+        return;
+    };
+    let Ok(mut status) = status_query.get_single_mut() else {
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        *rebind = RebindState::Captured;
+        status.sections[0].value.clear();
+        return;
+    }
+
+    let input = match device {
+        Device::Keyboard => keys
+            .get_just_pressed()
+            .next()
+            .map(|key| BindingInput::Key(*key)),
+        Device::Gamepad => gamepad_buttons
+            .get_just_pressed()
+            .next()
+            .map(|button| BindingInput::Gamepad(button.button_type)),
+    };
+    let Some(input) = input else {
+        return;
+    };
+
+    *rebind = RebindState::Captured;
+    status.sections[0].value = match controls.conflict(action, input) {
+        Some(other) => format!("{input} is already bound to {other}"),
+        None => {
+            controls.bind(action, input);
+            String::new()
+        }
+    };
+}
+
+fn handle_buttons(
+    rebind: Res<RebindState>,
+    buttons: Query<(&ControlsButton, &Interaction), Changed<Interaction>>,
+    mut event_writer: EventWriter<ControlsInteraction>,
+) {
+    if *rebind != RebindState::Idle {
+        return;
+    }
+    buttons.iter().for_each(|button| {
+        if let (button, Interaction::Pressed) = button {
+            event_writer.send(ControlsInteraction(*button));
+        }
+    });
+}
+
+fn ui_navigation(
+    navigation_actions: Query<&ActionState<SokobanActions>>,
+    mut rebind: ResMut<RebindState>,
+    mut selected: ResMut<SelectedBinding>,
+    mut event_writer: EventWriter<ControlsInteraction>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    match *rebind {
+        RebindState::Waiting(..) => return,
+        RebindState::Captured => {
+            *rebind = RebindState::Idle;
+            return;
+        }
+        RebindState::Idle => {}
+    }
+    let Ok(navigation_actions) = navigation_actions.get_single() else {
+        return;
+    };
+
+    let rows = ALL_ACTIONS.len() + 1;
+    if navigation_actions.just_pressed(SokobanActions::UiNavUp) {
+        selected.row = (selected.row + rows - 1) % rows;
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavDown) {
+        selected.row = (selected.row + 1) % rows;
+    }
+    let row_len = ControlsButton::row_len(selected.row);
+    selected.col = selected.col.min(row_len - 1);
+    if navigation_actions.just_pressed(SokobanActions::UiNavLeft) {
+        selected.col = (selected.col + row_len - 1) % row_len;
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavRight) {
+        selected.col = (selected.col + 1) % row_len;
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavSelect) {
+        event_writer.send(ControlsInteraction(ControlsButton::at(
+            selected.row,
+            selected.col,
+        )));
+    }
+    if navigation_actions.just_pressed(SokobanActions::Escape) {
+        game_state.set(GameState::MainMenu);
+    }
+}
+
+fn handle_interaction(
+    mut events: EventReader<ControlsInteraction>,
+    mut rebind: ResMut<RebindState>,
+    mut controls: ResMut<Controls>,
+    mut status_query: Query<&mut Text, With<ControlsStatus>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(mut status) = status_query.get_single_mut() else {
+        return;
+    };
+    for ev in events.read() {
+        status.sections[0].value.clear();
+        match **ev {
+            ControlsButton::Bind(action, device) => {
+                *rebind = RebindState::Waiting(action, device);
+                status.sections[0].value = match device {
+                    Device::Keyboard => format!("Press a key for {action} (Escape cancels)"),
+                    Device::Gamepad => format!("Press a button for {action} (Escape cancels)"),
+                };
+            }
+            ControlsButton::WasdDefaults => *controls = Controls::wasd(),
+            ControlsButton::ArrowDefaults => *controls = Controls::arrows(),
+            ControlsButton::Back => game_state.set(GameState::MainMenu),
+        }
+    }
+}
+
+fn button_labels(
+    controls: Res<Controls>,
+    buttons: Query<(&ControlsButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (button, children) in buttons.iter() {
+        let label = button.label(&controls);
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
+fn render_selected_border(
+    selected: Res<SelectedBinding>,
+    rebind: Res<RebindState>,
+    mut buttons: Query<(&ControlsButton, &mut BorderColor)>,
+) {
+    let selected = ControlsButton::at(selected.row, selected.col);
+    for (button, mut border_color) in buttons.iter_mut() {
+        *border_color = match *rebind {
+            RebindState::Waiting(action, device)
+                if *button == ControlsButton::Bind(action, device) =>
+            {
+                BorderColor(Color::YELLOW)
+            }
+            _ if *button == selected => BorderColor(Color::RED),
+            _ => BorderColor(Color::NONE),
+        };
+    }
+}
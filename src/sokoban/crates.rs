@@ -0,0 +1,44 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use super::{history::History, theme::ActiveTheme, DynamicBundle, Pos};
+
+/// Classic sokoban crate, moves exactly one tile when pushed and never slides.
+#[derive(Component, Clone)]
+pub struct Crate;
+
+pub struct SpawnCrate {
+    pub pos: Pos,
+    pub tilemap_entity: Entity,
+}
+
+impl SpawnCrate {
+    pub fn new(pos: Pos, tilemap_entity: Entity) -> Self {
+        Self {
+            pos,
+            tilemap_entity,
+        }
+    }
+}
+
+impl Command for SpawnCrate {
+    fn apply(self, world: &mut World) {
+        let texture = world.resource::<ActiveTheme>().crate_texture.clone();
+
+        world
+            .entity_mut(self.tilemap_entity)
+            .with_children(|child_builder| {
+                child_builder.spawn((
+                    Name::new("Crate"),
+                    Crate,
+                    self.pos,
+                    History::<Pos>::default(),
+                    DynamicBundle::default(),
+                    SpriteBundle {
+                        texture,
+                        transform: Transform::from_translation(2. * Vec3::Z),
+                        ..default()
+                    },
+                ));
+            });
+    }
+}
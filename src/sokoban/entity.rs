@@ -1,10 +1,15 @@
 use bevy::{ecs::system::Command, prelude::*};
 
 use super::{
+    animation::{BallAnimations, BallPose, PlayerAnimations, SpriteAnimation, SpriteLook},
     ball::Ball,
+    crates::Crate,
     history::{CurrentTime, HandleHistoryEvents, History, HistoryEvent},
+    inventory::Inventory,
+    laser::Mirror,
     level::LevelRoot,
-    player::{MovementTimer, Player},
+    motion::Resurfaced,
+    player::{Facing, MovementTimer, Player},
     DynamicBundle, Pos,
 };
 
@@ -56,15 +61,20 @@ pub struct DespawnSokobanEntityCommand(pub Entity);
 
 impl Command for DespawnSokobanEntityCommand {
     fn apply(self, world: &mut World) {
-        let (pos, history, texture, is_player, is_ball) =
-            if let Ok((pos, history, texture, is_player, is_ball)) = world
-                .query::<(&Pos, &History<Pos>, &Handle<Image>, Has<Player>, Has<Ball>)>()
+        let (pos, history, is_player, is_ball, is_crate) =
+            if let Ok((pos, history, is_player, is_ball, is_crate)) = world
+                .query::<(&Pos, &History<Pos>, Has<Player>, Has<Ball>, Has<Crate>)>()
                 .get(world, self.0)
             {
-                (*pos, history.clone(), texture.clone(), is_player, is_ball)
+                (*pos, history.clone(), is_player, is_ball, is_crate)
             } else {
                 todo!()
             };
+        let Some(look) = SpriteLook::of(world, self.0) else {
+            // Undo couldn't draw it again, so it stays around instead
+            bevy::log::error!("Sokoban entity {:?} has no sprite to despawn with", self.0);
+            return;
+        };
 
         let Ok(level_entity) = world
             .query_filtered::<Entity, With<LevelRoot>>()
@@ -73,6 +83,22 @@ impl Command for DespawnSokobanEntityCommand {
             todo!()
         };
 
+        // The clips have to match the sheet of the sprite, even if the theme changed since
+        let player_animations = world
+            .get::<PlayerAnimations>(self.0)
+            .cloned()
+            .unwrap_or_default();
+        let ball_animations = world
+            .get::<BallAnimations>(self.0)
+            .cloned()
+            .unwrap_or_default();
+        let mirror = world.get::<Mirror>(self.0).copied();
+        let inventory = world.get::<Inventory>(self.0).copied().unwrap_or_default();
+        let inventory_history = world
+            .get::<History<Inventory>>(self.0)
+            .cloned()
+            .unwrap_or_default();
+
         let current_time = *world.resource::<CurrentTime>();
         world.resource_scope(|world, mut command_history: Mut<CommandHistory>| {
             if is_player {
@@ -80,24 +106,56 @@ impl Command for DespawnSokobanEntityCommand {
                     entity: self.0,
                     pos,
                     history,
-                    texture,
+                    look,
                     level_entity,
                     bundle: (
                         Name::new("Player"),
                         Player,
                         DynamicBundle::default(),
                         MovementTimer::default(),
+                        Facing::default(),
+                        SpriteAnimation::default(),
+                        player_animations,
+                        inventory,
+                        inventory_history,
                     ),
                 };
                 command_history.push((*current_time, despawn.execute(world)));
+            } else if let Some(mirror) = mirror {
+                let despawn = DespawnSokobanEntity {
+                    entity: self.0,
+                    pos,
+                    history,
+                    look,
+                    level_entity,
+                    bundle: (Name::new("Mirror"), Ball, mirror, DynamicBundle::default()),
+                };
+                command_history.push((*current_time, despawn.execute(world)));
             } else if is_ball {
                 let despawn = DespawnSokobanEntity {
                     entity: self.0,
                     pos,
                     history,
-                    texture,
+                    look,
                     level_entity,
-                    bundle: (Name::new("Ball"), Ball, DynamicBundle::default()),
+                    bundle: (
+                        Name::new("Ball"),
+                        Ball,
+                        DynamicBundle::default(),
+                        SpriteAnimation::default(),
+                        ball_animations,
+                        BallPose::default(),
+                    ),
+                };
+                command_history.push((*current_time, despawn.execute(world)));
+            } else if is_crate {
+                let despawn = DespawnSokobanEntity {
+                    entity: self.0,
+                    pos,
+                    history,
+                    look,
+                    level_entity,
+                    bundle: (Name::new("Crate"), Crate, DynamicBundle::default()),
                 };
                 command_history.push((*current_time, despawn.execute(world)));
             }
@@ -113,7 +171,7 @@ where
     pub entity: Entity,
     pub pos: Pos,
     pub history: History<Pos>,
-    pub texture: Handle<Image>,
+    pub look: SpriteLook,
     pub level_entity: Entity,
     pub bundle: B,
 }
@@ -128,17 +186,30 @@ where
     }
 
     fn rollback(&self, cmds: &mut Commands) {
-        cmds.entity(self.level_entity).with_children(|parent| {
-            parent.spawn((
-                self.pos,
-                self.history.clone(),
-                SpriteBundle {
-                    texture: self.texture.clone(),
-                    transform: Transform::from_translation(Vec3::Z),
-                    ..default()
-                },
-                self.bundle.clone(),
-            ));
-        });
+        let transform = Transform::from_translation(Vec3::Z);
+        let mut entity = cmds.spawn((
+            self.pos,
+            self.history.clone(),
+            self.bundle.clone(),
+            Resurfaced,
+        ));
+        match &self.look {
+            SpriteLook::Image(texture) => entity.insert(SpriteBundle {
+                texture: texture.clone(),
+                transform,
+                ..default()
+            }),
+            SpriteLook::Sheet {
+                texture_atlas,
+                index,
+            } => entity.insert(SpriteSheetBundle {
+                texture_atlas: texture_atlas.clone(),
+                sprite: TextureAtlasSprite::new(*index),
+                transform,
+                ..default()
+            }),
+        };
+        let entity = entity.id();
+        cmds.entity(self.level_entity).add_child(entity);
     }
 }
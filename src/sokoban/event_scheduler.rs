@@ -55,6 +55,10 @@ where
         self.events
             .push_back((event, Timer::new(duration, TimerMode::Once)))
     }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
 }
 
 fn send_scheduled_events<E: Event>(
@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+
+use super::{
+    cleanup::DependOnState,
+    controls::{BoundAction, Controls},
+    AssetsCollection, GameState, SokobanActions, LEVEL_STATES,
+};
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LastInputDevice>()
+            .init_resource::<LastInputDevice>()
+            .add_systems(PreUpdate, detect_input_device)
+            .add_systems(
+                OnTransition {
+                    from: GameState::LevelTransition,
+                    to: GameState::Play,
+                },
+                spawn_control_hints,
+            )
+            .add_systems(
+                Update,
+                control_hints.run_if(in_state(GameState::Play).or_else(in_state(GameState::Pause))),
+            );
+    }
+}
+
+/// Device the player touched last, control hints are shown for it
+#[derive(Resource, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Resource)]
+pub enum LastInputDevice {
+    #[default]
+    Keyboard,
+    Gamepad,
+}
+
+/// Actions listed in the control hints during play
+const HINTS: [(SokobanActions, &str); 4] = [
+    (SokobanActions::Undo, "Undo"),
+    (SokobanActions::Reset, "Reset"),
+    (SokobanActions::FastForward, "Fast Forward"),
+    (SokobanActions::Escape, "Pause"),
+];
+
+/// Index of the button in `gamepad_glyphs.png`
+pub fn glyph_index(button: GamepadButtonType) -> usize {
+    use GamepadButtonType::*;
+    match button {
+        South => 0,
+        East => 1,
+        West => 2,
+        North => 3,
+        Start => 4,
+        Select => 5,
+        DPadUp => 6,
+        DPadRight => 7,
+        DPadDown => 8,
+        DPadLeft => 9,
+        LeftTrigger => 10,
+        RightTrigger => 11,
+        LeftTrigger2 => 12,
+        RightTrigger2 => 13,
+        _ => 14,
+    }
+}
+
+/// Name printed on the button of a common controller layout
+pub fn button_name(button: GamepadButtonType) -> String {
+    use GamepadButtonType::*;
+    match button {
+        South => "A",
+        East => "B",
+        West => "X",
+        North => "Y",
+        Start => "Start",
+        Select => "Select",
+        Mode => "Home",
+        DPadUp => "D-Pad Up",
+        DPadRight => "D-Pad Right",
+        DPadDown => "D-Pad Down",
+        DPadLeft => "D-Pad Left",
+        LeftTrigger => "LB",
+        RightTrigger => "RB",
+        LeftTrigger2 => "LT",
+        RightTrigger2 => "RT",
+        LeftThumb => "LS",
+        RightThumb => "RS",
+        other => return format!("{other:?}"),
+    }
+    .to_string()
+}
+
+fn detect_input_device(
+    mut last_device: ResMut<LastInputDevice>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    controls: Res<Controls>,
+) {
+    let stick_moved = gamepads.iter().any(|gamepad| {
+        [GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY]
+            .into_iter()
+            .any(|axis| {
+                gamepad_axes
+                    .get(GamepadAxis::new(gamepad, axis))
+                    .is_some_and(|value| value.abs() > controls.stick_deadzone)
+            })
+    });
+
+    let device = if keys.get_just_pressed().next().is_some()
+        || mouse_buttons.get_just_pressed().next().is_some()
+    {
+        LastInputDevice::Keyboard
+    } else if gamepad_buttons.get_just_pressed().next().is_some() || stick_moved {
+        LastInputDevice::Gamepad
+    } else {
+        return;
+    };
+
+    if *last_device != device {
+        *last_device = device;
+    }
+}
+
+#[derive(Component)]
+struct ControlHints;
+
+fn spawn_control_hints(mut cmds: Commands) {
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(10.),
+                left: Val::Px(10.),
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(6.),
+                ..default()
+            },
+            ..default()
+        },
+        ControlHints,
+        DependOnState(LEVEL_STATES.to_vec()),
+        Name::new("Control Hints"),
+    ));
+}
+
+fn control_hints(
+    mut cmds: Commands,
+    hints_query: Query<Entity, With<ControlHints>>,
+    added: Query<(), Added<ControlHints>>,
+    last_device: Res<LastInputDevice>,
+    controls: Res<Controls>,
+    assets: Res<AssetsCollection>,
+) {
+    if added.is_empty() && !last_device.is_changed() && !controls.is_changed() {
+        return;
+    }
+    let text_style = TextStyle {
+        font_size: 20.,
+        color: Color::BLACK,
+        ..default()
+    };
+
+    for hints in hints_query.iter() {
+        cmds.entity(hints)
+            .despawn_descendants()
+            .with_children(|parent| {
+                for (action, label) in HINTS {
+                    let binding = controls.binding(BoundAction::Sokoban(action));
+                    match *last_device {
+                        LastInputDevice::Gamepad => {
+                            let Some(button) = binding.and_then(|binding| binding.gamepad) else {
+                                continue;
+                            };
+                            parent.spawn(AtlasImageBundle {
+                                style: Style {
+                                    width: Val::Px(24.),
+                                    height: Val::Px(24.),
+                                    ..default()
+                                },
+                                texture_atlas: assets.gamepad_glyphs.clone(),
+                                texture_atlas_image: UiTextureAtlasImage {
+                                    index: glyph_index(button),
+                                    ..default()
+                                },
+                                ..default()
+                            });
+                        }
+                        LastInputDevice::Keyboard => {
+                            let Some(key) = binding.and_then(|binding| binding.keys.first()) else {
+                                continue;
+                            };
+                            parent.spawn(TextBundle::from_section(
+                                format!("[{key:?}]"),
+                                text_style.clone(),
+                            ));
+                        }
+                    }
+                    parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                }
+            });
+    }
+}
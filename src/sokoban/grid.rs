@@ -0,0 +1,164 @@
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    sprite::Anchor,
+};
+use bevy_ecs_tilemap::prelude::{TilemapGridSize, TilemapTileSize};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{theme::ActiveTheme, Pos};
+
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_asset_loader(GridConfigLoader)
+            .init_asset::<GridConfig>()
+            .init_resource::<GridConfig>()
+            .register_type::<GridConfig>()
+            .add_systems(PostUpdate, anchor_sprites);
+    }
+}
+
+/// Geometry of the tile grid, shipped with the art so tilesets of any size work.
+/// The file of the active theme is copied into the resource of the same type when a level
+/// is spawned, the tilemaps of a running level keep the geometry they were spawned with.
+#[derive(
+    Resource, Asset, TypePath, TypeUuid, Reflect, Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[uuid = "8e2f6a41-3c7d-4b59-a0e8-5f1d9c2b7a36"]
+#[reflect(Resource)]
+pub struct GridConfig {
+    /// Width and height of a tile in pixels
+    pub tile_size: f32,
+    /// Walls are drawn shifted by this many pixels, to give them some height
+    #[serde(default)]
+    pub wall_offset: (f32, f32),
+    /// Where the sprites of everything on the grid sit on their tile
+    #[serde(default)]
+    pub sprite_anchor: SpriteAnchor,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            tile_size: 8.,
+            wall_offset: (0., 2.),
+            sprite_anchor: SpriteAnchor::Center,
+        }
+    }
+}
+
+#[derive(Reflect, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpriteAnchor {
+    /// Centered on the tile
+    #[default]
+    Center,
+    /// Standing on the bottom edge of the tile, for sprites taller than a tile
+    Bottom,
+}
+
+impl GridConfig {
+    pub fn tile_size(&self) -> TilemapTileSize {
+        TilemapTileSize::from(Vec2::splat(self.tile_size))
+    }
+
+    pub fn grid_size(&self) -> TilemapGridSize {
+        self.tile_size().into()
+    }
+
+    pub fn wall_offset(&self) -> Vec2 {
+        Vec2::from(self.wall_offset)
+    }
+
+    /// Center of the tile at `pos`, tiles are centered on multiples of the tile size
+    pub fn to_world(&self, pos: &Pos) -> Vec2 {
+        Vec2::new(pos.x as f32, pos.y as f32) * self.tile_size
+    }
+
+    /// Tile containing the world position, may be outside of the level
+    pub fn to_tile(&self, world: Vec2) -> IVec2 {
+        (world / self.tile_size).round().as_ivec2()
+    }
+
+    /// Where the sprite of something standing at `pos` is placed
+    pub fn sprite_position(&self, pos: &Pos) -> Vec2 {
+        match self.sprite_anchor {
+            SpriteAnchor::Center => self.to_world(pos),
+            SpriteAnchor::Bottom => self.to_world(pos) - Vec2::Y * self.tile_size / 2.,
+        }
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        match self.sprite_anchor {
+            SpriteAnchor::Center => Anchor::Center,
+            SpriteAnchor::Bottom => Anchor::BottomCenter,
+        }
+    }
+}
+
+pub fn apply_grid_config(
+    mut config: ResMut<GridConfig>,
+    configs: Res<Assets<GridConfig>>,
+    theme: Res<ActiveTheme>,
+) {
+    if let Some(loaded) = configs.get(&theme.grid) {
+        if *config != *loaded {
+            *config = *loaded;
+        }
+    }
+}
+
+fn anchor_sprites(
+    config: Res<GridConfig>,
+    mut sprites: Query<&mut Sprite, With<Pos>>,
+    mut sheet_sprites: Query<&mut TextureAtlasSprite, With<Pos>>,
+) {
+    for mut sprite in sprites.iter_mut() {
+        if config.is_changed() || sprite.is_added() {
+            sprite.anchor = config.anchor();
+        }
+    }
+    for mut sprite in sheet_sprites.iter_mut() {
+        if config.is_changed() || sprite.is_added() {
+            sprite.anchor = config.anchor();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct GridConfigLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum GridConfigLoaderError {
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the ron: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for GridConfigLoader {
+    type Asset = GridConfig;
+    type Settings = ();
+    type Error = GridConfigLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, std::result::Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes::<GridConfig>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["grid"]
+    }
+}
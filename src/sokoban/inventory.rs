@@ -0,0 +1,234 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use super::{
+    cleanup::DependOnState,
+    history::{HandleHistoryEvents, History},
+    momentum::apply_momentum,
+    player::Player,
+    theme::ActiveTheme,
+    GameState, Pos, SokobanBlock, LEVEL_STATES,
+};
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Inventory>()
+            .register_type::<Key>()
+            .register_type::<Lock>()
+            .add_event::<UseKey>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::LevelTransition,
+                    to: GameState::Play,
+                },
+                spawn_inventory_hud,
+            )
+            .add_systems(
+                FixedUpdate,
+                collect_keys
+                    .after(apply_momentum)
+                    .run_if(in_state(GameState::Play)),
+            )
+            .add_systems(
+                Update,
+                (
+                    use_key.after(HandleHistoryEvents),
+                    key_visual,
+                    lock_visual,
+                    inventory_hud,
+                )
+                    .run_if(in_state(GameState::Play)),
+            );
+    }
+}
+
+#[derive(Component, Clone, Copy, Default, Debug, Reflect)]
+pub struct Inventory {
+    pub keys: usize,
+}
+
+#[derive(Component, Clone, Copy, Default, Debug, Reflect)]
+pub struct Key {
+    pub collected: bool,
+}
+
+#[derive(Component, Clone, Copy, Default, Debug, Reflect)]
+pub struct Lock {
+    pub open: bool,
+}
+
+/// Sent when the player walks into a lock while holding a key.
+/// Handled after history has been recorded so the step can be undone.
+#[derive(Event)]
+pub struct UseKey(pub Entity);
+
+#[derive(Component)]
+struct KeyCounter;
+
+fn collect_keys(
+    mut player_query: Query<(&Pos, &mut Inventory), With<Player>>,
+    mut key_query: Query<(&Pos, &mut Key)>,
+) {
+    for (player_pos, mut inventory) in player_query.iter_mut() {
+        for (key_pos, mut key) in key_query.iter_mut() {
+            if !key.collected && key_pos == player_pos {
+                key.collected = true;
+                inventory.keys += 1;
+            }
+        }
+    }
+}
+
+fn use_key(
+    mut use_key_events: EventReader<UseKey>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+    mut lock_query: Query<&mut Lock>,
+) {
+    let Ok(mut inventory) = player_query.get_single_mut() else {
+        return;
+    };
+    for UseKey(lock) in use_key_events.read() {
+        let Ok(mut lock) = lock_query.get_mut(*lock) else {
+            continue;
+        };
+        if !lock.open && inventory.keys > 0 {
+            inventory.keys -= 1;
+            lock.open = true;
+        }
+    }
+}
+
+fn key_visual(mut key_query: Query<(&Key, &mut Visibility), Changed<Key>>) {
+    for (key, mut visibility) in key_query.iter_mut() {
+        *visibility = if key.collected {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+fn lock_visual(mut lock_query: Query<(&Lock, &mut Visibility), Changed<Lock>>) {
+    for (lock, mut visibility) in lock_query.iter_mut() {
+        *visibility = if lock.open {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+fn spawn_inventory_hud(mut cmds: Commands) {
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                left: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        },
+        DependOnState(LEVEL_STATES.to_vec()),
+        Name::new("Inventory HUD"),
+    ))
+    .with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "Keys: 0",
+                TextStyle {
+                    font_size: 28.,
+                    color: Color::BLACK,
+                    ..default()
+                },
+            ),
+            KeyCounter,
+        ));
+    });
+}
+
+fn inventory_hud(
+    player_query: Query<&Inventory, (With<Player>, Changed<Inventory>)>,
+    mut counter_query: Query<&mut Text, With<KeyCounter>>,
+) {
+    let Ok(inventory) = player_query.get_single() else {
+        return;
+    };
+    for mut text in counter_query.iter_mut() {
+        text.sections[0].value = format!("Keys: {}", inventory.keys);
+    }
+}
+
+pub struct SpawnKey {
+    pos: Pos,
+    tilemap_entity: Entity,
+}
+
+impl SpawnKey {
+    pub fn new(pos: Pos, tilemap_entity: Entity) -> Self {
+        Self {
+            pos,
+            tilemap_entity,
+        }
+    }
+}
+
+impl Command for SpawnKey {
+    fn apply(self, world: &mut World) {
+        let texture = world.resource::<ActiveTheme>().key.clone();
+
+        world
+            .entity_mut(self.tilemap_entity)
+            .with_children(|child_builder| {
+                child_builder.spawn((
+                    Name::new("Key"),
+                    Key::default(),
+                    History::<Key>::default(),
+                    self.pos,
+                    SpriteBundle {
+                        texture,
+                        transform: Transform::from_translation(Vec3::Z),
+                        ..default()
+                    },
+                ));
+            });
+    }
+}
+
+pub struct SpawnLock {
+    pos: Pos,
+    tilemap_entity: Entity,
+}
+
+impl SpawnLock {
+    pub fn new(pos: Pos, tilemap_entity: Entity) -> Self {
+        Self {
+            pos,
+            tilemap_entity,
+        }
+    }
+}
+
+impl Command for SpawnLock {
+    fn apply(self, world: &mut World) {
+        let texture = world.resource::<ActiveTheme>().lock.clone();
+
+        world
+            .entity_mut(self.tilemap_entity)
+            .with_children(|child_builder| {
+                child_builder.spawn((
+                    Name::new("Lock"),
+                    Lock::default(),
+                    History::<Lock>::default(),
+                    SokobanBlock::Static,
+                    self.pos,
+                    SpriteBundle {
+                        texture,
+                        transform: Transform::from_translation(2. * Vec3::Z),
+                        ..default()
+                    },
+                ));
+            });
+    }
+}
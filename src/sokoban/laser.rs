@@ -0,0 +1,202 @@
+use bevy::{ecs::system::Command, prelude::*, utils::HashSet};
+
+use super::{
+    ball::Ball,
+    collision::{sync_collision_map, CollisionMap},
+    grid::GridConfig,
+    history::History,
+    level::LevelRoot,
+    momentum::transfer_momentum,
+    theme::ActiveTheme,
+    tile_behaviour::Lamp,
+    Dir, DynamicBundle, GameState, Pos,
+};
+
+pub struct LaserPlugin;
+
+impl Plugin for LaserPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            // Several fixed steps can run in one frame when moves resolve
+            // instantly, receivers have to be lit between them like they are
+            // between frames
+            trace_beams
+                .after(sync_collision_map)
+                .before(transfer_momentum)
+                .run_if(in_state(GameState::Play)),
+        )
+        .add_systems(
+            PostUpdate,
+            (mirror_visual, trace_beams.after(sync_collision_map))
+                .run_if(in_state(GameState::Play)),
+        );
+    }
+}
+
+/// Shoots a beam in a fixed direction
+#[derive(Component, Clone, Copy)]
+pub struct LaserEmitter(pub Dir);
+
+/// Lamp that is lit while a beam hits it
+#[derive(Component)]
+pub struct LaserReceiver;
+
+/// Pushable ball that turns beams by 90°
+#[derive(Component, Clone, Copy, Debug)]
+pub enum Mirror {
+    /// Shaped like `/`
+    Forward,
+    /// Shaped like `\`
+    Backward,
+}
+
+impl Mirror {
+    pub fn reflect(&self, dir: Dir) -> Dir {
+        use Dir::*;
+        match (self, dir) {
+            (Mirror::Forward, Up) => Right,
+            (Mirror::Forward, Right) => Up,
+            (Mirror::Forward, Down) => Left,
+            (Mirror::Forward, Left) => Down,
+            (Mirror::Backward, Up) => Left,
+            (Mirror::Backward, Left) => Up,
+            (Mirror::Backward, Down) => Right,
+            (Mirror::Backward, Right) => Down,
+        }
+    }
+}
+
+#[derive(Component)]
+struct BeamSegment;
+
+fn mirror_visual(mut mirror_query: Query<(&Mirror, &mut Sprite), Added<Mirror>>) {
+    for (mirror, mut sprite) in mirror_query.iter_mut() {
+        sprite.flip_x = matches!(mirror, Mirror::Backward);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn trace_beams(
+    mut cmds: Commands,
+    emitter_query: Query<(&Pos, &LaserEmitter)>,
+    mirror_query: Query<&Mirror>,
+    mut receiver_query: Query<(Entity, &mut Lamp), With<LaserReceiver>>,
+    segment_query: Query<Entity, With<BeamSegment>>,
+    level_root: Query<Entity, With<LevelRoot>>,
+    moved: Query<(), Changed<Pos>>,
+    mut removed: RemovedComponents<Pos>,
+    collision: Res<CollisionMap>,
+    grid: Res<GridConfig>,
+) {
+    let removed = removed.read().count() > 0;
+    if moved.is_empty() && !removed {
+        return;
+    }
+    let Ok(level_root) = level_root.get_single() else {
+        return;
+    };
+
+    for segment in segment_query.iter() {
+        cmds.entity(segment).despawn_recursive();
+    }
+
+    let mut hit = HashSet::default();
+    let mut segments = Vec::new();
+    for (pos, emitter) in emitter_query.iter() {
+        let mut dir = emitter.0;
+        let mut current = IVec2::from(pos);
+        // Mirrors can form loops, stop once we pass a tile in the same direction again
+        let mut visited = HashSet::default();
+        loop {
+            current += IVec2::from(dir);
+            if !collision.in_bounds(current) || !visited.insert((current, dir)) {
+                break;
+            }
+            match collision.get(current) {
+                Some((entity, _)) => {
+                    if let Ok(mirror) = mirror_query.get(entity) {
+                        dir = mirror.reflect(dir);
+                        continue;
+                    }
+                    hit.insert(entity);
+                    break;
+                }
+                None => segments.push((current, dir)),
+            }
+        }
+    }
+
+    for (entity, mut lamp) in receiver_query.iter_mut() {
+        let lit = hit.contains(&entity);
+        if lamp.0 != lit {
+            lamp.0 = lit;
+        }
+    }
+
+    cmds.entity(level_root).with_children(|parent| {
+        for (pos, dir) in segments {
+            // Beams are a quarter of a tile thick
+            let size = match dir {
+                Dir::Up | Dir::Down => Vec2::new(grid.tile_size / 4., grid.tile_size),
+                Dir::Left | Dir::Right => Vec2::new(grid.tile_size, grid.tile_size / 4.),
+            };
+            let translation = grid
+                .to_world(&Pos::new(pos.x as u32, pos.y as u32))
+                .extend(1.5);
+            parent.spawn((
+                Name::new("Beam"),
+                BeamSegment,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::RED,
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(translation),
+                    ..default()
+                },
+            ));
+        }
+    });
+}
+
+pub struct SpawnMirror {
+    pub pos: Pos,
+    pub mirror: Mirror,
+    pub tilemap_entity: Entity,
+}
+
+impl SpawnMirror {
+    pub fn new(pos: Pos, mirror: Mirror, tilemap_entity: Entity) -> Self {
+        Self {
+            pos,
+            mirror,
+            tilemap_entity,
+        }
+    }
+}
+
+impl Command for SpawnMirror {
+    fn apply(self, world: &mut World) {
+        let texture = world.resource::<ActiveTheme>().mirror.clone();
+
+        world
+            .entity_mut(self.tilemap_entity)
+            .with_children(|child_builder| {
+                child_builder.spawn((
+                    Name::new("Mirror"),
+                    Ball,
+                    self.mirror,
+                    self.pos,
+                    History::<Pos>::default(),
+                    DynamicBundle::default(),
+                    SpriteBundle {
+                        texture,
+                        transform: Transform::from_translation(2. * Vec3::Z),
+                        ..default()
+                    },
+                ));
+            });
+    }
+}
@@ -5,19 +5,25 @@ use bevy::{
 };
 use bevy_asset_loader::prelude::AssetCollection;
 use bevy_ecs_tilemap::prelude::*;
-use bevy_pile::grid::Grid;
 use serde::Deserialize;
 use thiserror::Error;
 
 use super::{
+    autotile::{Autotile, NeighbourGrid},
     ball::SpawnBall,
+    camera::fit_camera_to_level,
     cleanup::DependOnState,
     collision::init_collision_map,
+    crates::SpawnCrate,
+    grid::{apply_grid_config, GridConfig},
+    history::History,
+    inventory::{SpawnKey, SpawnLock},
+    laser::{LaserEmitter, LaserReceiver, Mirror, SpawnMirror},
     level_select::CurrentLevel,
     player::SpawnPlayer,
-    tile_behaviour::{Lamp, Rubber, Sand, SpawnGoal, Void},
-    util::DIRS,
-    AssetsCollection, GameState, Pos, SokobanBlock,
+    theme::ActiveTheme,
+    tile_behaviour::{Ice, Lamp, LampGroup, Rubber, Sand, SpawnGoal, Switch, Void},
+    Dir, GameState, Pos, SokobanBlock, LEVEL_STATES,
 };
 
 pub struct LevelPlugin;
@@ -33,7 +39,12 @@ impl Plugin for LevelPlugin {
                     from: GameState::LevelTransition,
                     to: GameState::Play,
                 },
-                (spawn_level, apply_deferred, center_camera_on_level)
+                (
+                    apply_grid_config,
+                    spawn_level,
+                    apply_deferred,
+                    fit_camera_to_level,
+                )
                     .chain()
                     .before(init_collision_map),
             )
@@ -53,20 +64,19 @@ pub struct LevelCollection {
     pub levels: Handle<Levels>,
 }
 
-fn center_camera_on_level(
-    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
-    tilemap_q: Query<&TilemapSize, (With<TileStorage>, Without<Camera>)>,
-) {
-    let Ok((mut camera_transform, mut projection)) = camera_q.get_single_mut() else {
-        return;
-    };
+impl LevelCollection {
+    /// Asset path of the level pack, identifies it in the music manifest and records
+    pub fn pack(&self, asset_server: &AssetServer) -> String {
+        asset_server
+            .get_path(self.levels.id())
+            .map(|path| path.to_string())
+            .unwrap_or_default()
+    }
 
-    let Ok(size) = tilemap_q.get_single() else {
-        return;
-    };
-    let center = Vec2::from(*size) * 8. / 2. - 4.;
-    camera_transform.translation = center.extend(camera_transform.translation.z);
-    projection.scale = 0.15;
+    /// Number of levels in the pack, 0 while it is not loaded
+    pub fn level_count(&self, levels: &Assets<Levels>) -> usize {
+        levels.get(&self.levels).map_or(0, |levels| levels.len())
+    }
 }
 
 #[derive(Component)]
@@ -76,17 +86,38 @@ fn spawn_level(
     mut cmds: Commands,
     current_level: Res<CurrentLevel>,
     levels_assets: Res<Assets<Levels>>,
-    asset_collection: Res<AssetsCollection>,
+    theme: Res<ActiveTheme>,
+    autotiles: Res<Assets<Autotile>>,
     level_collection: Res<LevelCollection>,
+    mut next_state: ResMut<NextState<GameState>>,
+    grid: Res<GridConfig>,
 ) {
     let levels_handle = &level_collection.levels;
 
     let levels = levels_assets
         .get(levels_handle)
         .expect("Level handle should be loaded");
-    let level = levels
-        .get(**current_level)
-        .expect("Current level should only ever be set to a valid level");
+    let Some(level) = levels.get(**current_level) else {
+        // The pack can change under a running game, e.g. when it is hot reloaded
+        bevy::log::error!(
+            "Level {} does not exist, the pack has {} levels",
+            **current_level,
+            levels.len()
+        );
+        next_state.set(GameState::LevelSelect);
+        return;
+    };
+
+    let Some(autotile) = autotiles.get(&theme.wall_autotile) else {
+        // The autotile of a theme can fail to load, e.g. when it has an invalid rule
+        bevy::log::error!("Autotile of the {} theme is not loaded", theme.name);
+        next_state.set(GameState::LevelSelect);
+        return;
+    };
+    let neighbours = NeighbourGrid::new(
+        level.size,
+        level.tiles.iter().map(TileKind::is_static).collect(),
+    );
 
     let size = TilemapSize::from(level.size);
     let mut storage = TileStorage::empty(size);
@@ -94,15 +125,15 @@ fn spawn_level(
     let level_root = cmds
         .spawn((
             SpatialBundle::default(),
-            DependOnState(vec![GameState::Play, GameState::Pause]),
+            DependOnState(LEVEL_STATES.to_vec()),
             Name::new("Level Root"),
             LevelRoot,
         ))
         .id();
     let tilemap_entity = cmds.spawn_empty().id();
     let wall_tilemap_entity = cmds.spawn_empty().id();
-    let tile_size = TilemapTileSize::from(Vec2::splat(8.));
-    let grid_size = tile_size.into();
+    let tile_size = grid.tile_size();
+    let grid_size = grid.grid_size();
     let map_type = TilemapType::Square;
 
     for (idx, tile) in level.tiles.iter().enumerate() {
@@ -112,9 +143,13 @@ fn spawn_level(
         };
 
         let (texture_index, flip) = if matches!(tile, TileKind::Wall) {
-            calculate_wall_index(UVec2::from(position).as_ivec2(), level)
+            let wall = autotile.wall(neighbours.get(UVec2::from(position).as_ivec2()));
+            (wall.index, wall.flip)
         } else {
-            (TileTextureIndex::from(*tile), TileFlip::default())
+            (
+                TileTextureIndex(theme.tile_indices.get(*tile)),
+                TileFlip::default(),
+            )
         };
         let tilemap_id = if matches!(tile, TileKind::Wall) {
             TilemapId(wall_tilemap_entity)
@@ -141,7 +176,7 @@ fn spawn_level(
                     Name::new("Subwall"),
                     TileBundle {
                         position,
-                        texture_index: TileTextureIndex(14),
+                        texture_index: autotile.subwall(),
                         tilemap_id: TilemapId(wall_tilemap_entity),
                         ..default()
                     },
@@ -155,10 +190,16 @@ fn spawn_level(
         if !matches!(tile, TileKind::Floor) {
             tile_cmds.insert(pos);
         }
+        if let Some(group) = level.wiring.get(idx).copied().flatten() {
+            tile_cmds.insert(LampGroup(group));
+        }
         match tile {
             TileKind::Sand => {
                 tile_cmds.insert((Name::new("Sand"), Sand));
             }
+            TileKind::Ice => {
+                tile_cmds.insert((Name::new("Ice"), Ice));
+            }
             TileKind::Rubber => {
                 tile_cmds.insert((Name::new("Rubber"), SokobanBlock::Static, Rubber));
             }
@@ -178,11 +219,59 @@ fn spawn_level(
                 cmds.add(SpawnBall::new(pos, level_root))
             }
             TileKind::LampOff => {
-                tile_cmds.insert((Name::new("Lamp"), SokobanBlock::Static, Lamp(false)));
+                tile_cmds.insert((
+                    Name::new("Lamp"),
+                    SokobanBlock::Static,
+                    Lamp(false),
+                    History::<Lamp>::default(),
+                ));
             }
             TileKind::LampOn => {
-                tile_cmds.insert((Name::new("Lamp"), SokobanBlock::Static, Lamp(true)));
+                tile_cmds.insert((
+                    Name::new("Lamp"),
+                    SokobanBlock::Static,
+                    Lamp(true),
+                    History::<Lamp>::default(),
+                ));
+            }
+            TileKind::Emitter(dir) => {
+                tile_cmds.insert((
+                    Name::new("Laser Emitter"),
+                    SokobanBlock::Static,
+                    LaserEmitter(*dir),
+                ));
+            }
+            TileKind::Receiver => {
+                tile_cmds.insert((
+                    Name::new("Laser Receiver"),
+                    SokobanBlock::Static,
+                    Lamp(false),
+                    History::<Lamp>::default(),
+                    LaserReceiver,
+                ));
+            }
+            TileKind::MirrorForward => cmds.add(SpawnMirror::new(pos, Mirror::Forward, level_root)),
+            TileKind::MirrorBackward => {
+                cmds.add(SpawnMirror::new(pos, Mirror::Backward, level_root))
             }
+            TileKind::Switch => {
+                tile_cmds.insert((
+                    Name::new("Switch"),
+                    SokobanBlock::Static,
+                    Switch(false),
+                    History::<Switch>::default(),
+                ));
+            }
+            TileKind::Crate => cmds.add(SpawnCrate::new(pos, level_root)),
+            TileKind::CrateGoal => {
+                cmds.add(SpawnGoal::crate_only(pos, level_root));
+            }
+            TileKind::CrateOnCrateGoal => {
+                cmds.add(SpawnGoal::crate_only(pos, level_root));
+                cmds.add(SpawnCrate::new(pos, level_root))
+            }
+            TileKind::Key => cmds.add(SpawnKey::new(pos, level_root)),
+            TileKind::Lock => cmds.add(SpawnLock::new(pos, level_root)),
             TileKind::Floor => {}
         };
         if matches!(tile, TileKind::Wall) {
@@ -199,7 +288,7 @@ fn spawn_level(
             map_type,
             size,
             storage,
-            texture: TilemapTexture::Single(asset_collection.tiles.clone()),
+            texture: TilemapTexture::Single(theme.tiles.clone()),
             tile_size,
             ..default()
         },
@@ -211,9 +300,9 @@ fn spawn_level(
             map_type,
             size,
             storage: wall_storage,
-            texture: TilemapTexture::Single(asset_collection.wall_tiles.clone()),
+            texture: TilemapTexture::Single(theme.walls.clone()),
             tile_size,
-            transform: Transform::from_xyz(0., 2., 1.),
+            transform: Transform::from_translation(grid.wall_offset().extend(1.)),
             ..default()
         },
         Name::new(format!("Wall Level {}", **current_level)),
@@ -223,87 +312,6 @@ fn spawn_level(
     cmds.entity(level_root).add_child(wall_tilemap_entity);
 }
 
-fn calculate_wall_index(pos: IVec2, level: &Level) -> (TileTextureIndex, TileFlip) {
-    let level_grid = Grid::from_raw(level.size.as_ivec2(), level.tiles.clone());
-    let [n, ne, e, se, s, sw, w, nw]: [bool; 8] = DIRS
-        .iter()
-        .map(|dir| {
-            let npos = pos + *dir;
-            level_grid.get(npos).map_or(false, |tile| !tile.is_static())
-        })
-        .collect::<Vec<bool>>()
-        .try_into()
-        .unwrap();
-
-    let diag_c: usize = [ne, se, sw, nw].iter().map(|x| *x as usize).sum();
-    let card_c: usize = [n, e, s, w].iter().map(|x| *x as usize).sum();
-
-    let flip = TileFlip {
-        x: e,
-        y: s,
-        d: e || w,
-    };
-    let flip_inv = TileFlip {
-        x: e,
-        y: s,
-        d: !e || !w,
-    };
-    let two_diag = TileFlip {
-        x: ne,
-        y: sw,
-        d: (nw && sw) || (ne && se), // Both vertical
-    };
-    let zero_flip_diag = TileFlip {
-        x: ne || se,
-        y: sw || se,
-        ..default()
-    };
-    let three_diag_flip = TileFlip {
-        x: e || n && se || s && ne,
-        y: s || e && sw || w && se,
-        d: e || w,
-    };
-    let (id, flip) = match card_c {
-        0 => match diag_c {
-            0 => (8, TileFlip::default()),
-            1 => (9, zero_flip_diag),
-            2 => {
-                if (nw && se) || (ne && sw) {
-                    (11, two_diag)
-                } else {
-                    (10, two_diag)
-                }
-            }
-            3 => (12, zero_flip_diag),
-            4 => (13, TileFlip::default()),
-            _ => unreachable!(),
-        },
-        1 => {
-            if diag_c == 4 {
-                (2, flip)
-            } else if diag_c == 3 {
-                (1, three_diag_flip)
-            } else {
-                (0, flip)
-            }
-        }
-        2 => {
-            if (n && s) || (w && e) {
-                (5, flip_inv)
-            } else if (n && w && se) || (n && e && sw) || (s && w && ne) || (s && e && nw) {
-                (4, flip)
-            } else {
-                (3, flip)
-            }
-        }
-        3 => (6, flip_inv),
-        4 => (7, TileFlip::default()),
-        _ => unreachable!(),
-    };
-
-    (TileTextureIndex(id), flip)
-}
-
 fn reload_on_change(
     mut asset_events: EventReader<AssetEvent<Levels>>,
     mut game_state: ResMut<NextState<GameState>>,
@@ -333,13 +341,30 @@ pub enum TileKind {
     BallGoal,
     LampOff,
     LampOn,
+    Key,
+    Lock,
+    Ice,
+    Crate,
+    CrateGoal,
+    CrateOnCrateGoal,
+    Switch,
+    Emitter(Dir),
+    Receiver,
+    MirrorForward,
+    MirrorBackward,
 }
 
 impl TileKind {
     pub fn is_static(&self) -> bool {
         matches!(
             self,
-            TileKind::Wall | TileKind::Rubber | TileKind::LampOff | TileKind::LampOn
+            TileKind::Wall
+                | TileKind::Rubber
+                | TileKind::LampOff
+                | TileKind::LampOn
+                | TileKind::Switch
+                | TileKind::Emitter(_)
+                | TileKind::Receiver
         )
     }
 }
@@ -359,6 +384,20 @@ impl From<u8> for TileKind {
             b'B' => BallGoal,
             b'l' => LampOff,
             b'L' => LampOn,
+            b'k' => Key,
+            b'X' => Lock,
+            b'*' => Ice,
+            b'c' => Crate,
+            b'o' => CrateGoal,
+            b'C' => CrateOnCrateGoal,
+            b's' => Switch,
+            b'^' => Emitter(Dir::Up),
+            b'>' => Emitter(Dir::Right),
+            b'v' => Emitter(Dir::Down),
+            b'<' => Emitter(Dir::Left),
+            b'r' => Receiver,
+            b'/' => MirrorForward,
+            b'\\' => MirrorBackward,
             _ => {
                 bevy::log::warn!("Couldnt parse tile kind defaulting to wall tile");
                 Wall
@@ -367,25 +406,6 @@ impl From<u8> for TileKind {
     }
 }
 
-impl From<TileKind> for TileTextureIndex {
-    fn from(value: TileKind) -> Self {
-        let id = match value {
-            TileKind::Wall => 0,
-            TileKind::Floor => 0,
-            TileKind::Void => 2,
-            TileKind::Ball => 0,
-            TileKind::Rubber => 3,
-            TileKind::Sand => 1,
-            TileKind::Player => 0,
-            TileKind::Goal => 0,
-            TileKind::BallGoal => 0,
-            TileKind::LampOff => 4,
-            TileKind::LampOn => 5,
-        };
-        Self(id)
-    }
-}
-
 #[derive(TypePath, TypeUuid, Debug, Deserialize, Deref, DerefMut, Asset)]
 #[uuid = "39cadc56-aa9c-4543-8540-a018b74b5052"]
 pub struct Levels(pub Vec<Level>);
@@ -403,6 +423,12 @@ pub enum LevelLoaderError {
     Io(#[from] std::io::Error),
     #[error("Could not parse the ron: {0}")]
     RonError(#[from] ron::error::SpannedError),
+    #[error(
+        "The wiring of level {level} should have {} columns and {} rows like its tiles",
+        .size.x,
+        .size.y
+    )]
+    InvalidWiring { level: usize, size: UVec2 },
 }
 
 impl AssetLoader for LevelLoader {
@@ -424,7 +450,8 @@ impl AssetLoader for LevelLoader {
             let levels = string_levels
                 .0
                 .iter()
-                .map(|string_level| {
+                .enumerate()
+                .map(|(index, string_level)| {
                     let tiles = string_level
                         .tiles
                         .replace(['\n', ' '], "")
@@ -436,12 +463,41 @@ impl AssetLoader for LevelLoader {
                         .rev()
                         .flat_map(|chunk| chunk.to_vec())
                         .collect::<Vec<TileKind>>();
-                    Level {
+                    // A wiring layer that doesn't line up would wire the wrong tiles
+                    let rows = string_level
+                        .wiring
+                        .lines()
+                        .map(|row| row.replace(' ', ""))
+                        .filter(|row| !row.is_empty())
+                        .collect::<Vec<String>>();
+                    let size = string_level.size;
+                    if !rows.is_empty()
+                        && (rows.len() != size.y as usize
+                            || rows
+                                .iter()
+                                .any(|row| row.chars().count() != size.x as usize))
+                    {
+                        return Err(LevelLoaderError::InvalidWiring { level: index, size });
+                    }
+                    // Digits in the wiring layer assign the tile below to that lamp group
+                    let wiring = rows
+                        .concat()
+                        .chars()
+                        .map(|c| c.to_digit(10).map(|group| group as u8))
+                        .collect::<Vec<Option<u8>>>()
+                        .chunks_exact(string_level.size.x as usize)
+                        .rev()
+                        .flat_map(|chunk| chunk.to_vec())
+                        .collect::<Vec<Option<u8>>>();
+                    Ok(Level {
                         tiles,
+                        wiring,
                         size: string_level.size,
-                    }
+                        name: string_level.name.clone(),
+                        par: string_level.par,
+                    })
                 })
-                .collect::<Vec<Level>>();
+                .collect::<Result<Vec<Level>, _>>()?;
 
             Ok(Levels(levels))
         })
@@ -455,11 +511,21 @@ impl AssetLoader for LevelLoader {
 #[derive(Deserialize, Debug, Reflect)]
 pub struct Level {
     pub tiles: Vec<TileKind>,
+    pub wiring: Vec<Option<u8>>,
     pub size: UVec2,
+    pub name: String,
+    /// Moves the level can be solved in
+    pub par: Option<u32>,
 }
 
 #[derive(Deserialize, Debug, Reflect)]
 struct StringLevel {
     pub tiles: String,
+    #[serde(default)]
+    pub wiring: String,
     pub size: UVec2,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub par: Option<u32>,
 }
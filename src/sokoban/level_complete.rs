@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::*, time::Stopwatch};
+use leafwing_input_manager::prelude::ActionState;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    cleanup::DependOnState,
+    config,
+    level::{LevelCollection, Levels},
+    level_select::CurrentLevel,
+    replay::{Recording, RecordingSummary, ReplayPlayback},
+    theme::ActiveTheme,
+    ui::NineSliceButtonText,
+    GameState, SokobanActions,
+};
+
+const CURRENT_STATE: GameState = GameState::LevelComplete;
+const RECORDS_FILE: &str = "records.ron";
+
+pub struct LevelCompletePlugin;
+
+impl Plugin for LevelCompletePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PersonalBests(config::load_or_default(RECORDS_FILE)))
+            .init_resource::<PlayTime>()
+            .init_resource::<LastReplay>()
+            .init_resource::<Summary>()
+            .init_resource::<SelectedButton>()
+            .add_event::<InteractionEvent>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::LevelTransition,
+                    to: GameState::Play,
+                },
+                reset_play_time,
+            )
+            .add_systems(
+                OnEnter(CURRENT_STATE),
+                (finish_level, spawn_summary).chain(),
+            )
+            .add_systems(Update, tick_play_time.run_if(in_state(GameState::Play)))
+            .add_systems(
+                Update,
+                (
+                    handle_buttons,
+                    ui_navigation,
+                    handle_interaction,
+                    render_selected_border,
+                )
+                    .chain()
+                    .run_if(in_state(CURRENT_STATE)),
+            );
+    }
+}
+
+/// Time spent playing the current attempt, pausing stops it
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct PlayTime(Stopwatch);
+
+/// Best result of every level that was completed, keyed by pack and level index
+#[derive(Resource, Default)]
+pub struct PersonalBests(HashMap<String, LevelResult>);
+
+impl PersonalBests {
+    fn key(pack: &str, level: usize) -> String {
+        format!("{pack}#{level}")
+    }
+
+    pub fn get(&self, pack: &str, level: usize) -> Option<LevelResult> {
+        self.0.get(&Self::key(pack, level)).copied()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LevelResult {
+    pub moves: usize,
+    pub pushes: usize,
+    pub seconds: f32,
+}
+
+impl LevelResult {
+    fn beats(&self, other: &LevelResult) -> bool {
+        (self.moves, self.seconds) < (other.moves, other.seconds)
+    }
+}
+
+/// Recording of the last completed attempt, for watching it back
+#[derive(Resource, Default)]
+struct LastReplay(Recording);
+
+fn reset_play_time(mut play_time: ResMut<PlayTime>) {
+    play_time.reset();
+}
+
+fn tick_play_time(mut play_time: ResMut<PlayTime>, time: Res<Time>) {
+    play_time.tick(time.delta());
+}
+
+/// What the summary shows, taken when the level was completed
+#[derive(Resource, Default)]
+struct Summary {
+    stats: RecordingSummary,
+    seconds: f32,
+    par: Option<u32>,
+    best: Option<LevelResult>,
+    new_best: bool,
+    /// The level was the last of its pack
+    last_level: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn finish_level(
+    mut summary: ResMut<Summary>,
+    recording: Res<Recording>,
+    playback: Res<ReplayPlayback>,
+    play_time: Res<PlayTime>,
+    mut bests: ResMut<PersonalBests>,
+    mut last_replay: ResMut<LastReplay>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    asset_server: Res<AssetServer>,
+) {
+    let stats = recording.summary();
+    let seconds = play_time.elapsed_secs();
+    let par = levels
+        .get(&level_collection.levels)
+        .and_then(|levels| levels.get(**current_level))
+        .and_then(|level| level.par);
+
+    let key = PersonalBests::key(&level_collection.pack(&asset_server), **current_level);
+    let result = LevelResult {
+        moves: stats.moves,
+        pushes: stats.pushes,
+        seconds,
+    };
+    let previous = bests.0.get(&key).copied();
+    // Watching a replay doesn't set records or replace the replay
+    let new_best =
+        !playback.is_watching() && previous.map_or(true, |previous| result.beats(&previous));
+    if new_best {
+        bests.0.insert(key, result);
+        config::save_or_warn(RECORDS_FILE, &bests.0);
+    }
+    if !playback.is_watching() {
+        last_replay.0 = recording.clone();
+    }
+
+    *summary = Summary {
+        stats,
+        seconds,
+        par,
+        best: previous,
+        new_best,
+        last_level: !current_level.has_next(level_collection.level_count(&levels)),
+    };
+}
+
+pub fn format_time(seconds: f32) -> String {
+    format!("{}:{:04.1}", (seconds / 60.) as u32, seconds % 60.)
+}
+
+impl Summary {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Moves: {}", self.stats.moves)];
+        if let Some(par) = self.par {
+            let par = par as usize;
+            lines.push(match self.stats.moves.cmp(&par) {
+                std::cmp::Ordering::Less => format!("Par {par}, {} under!", par - self.stats.moves),
+                std::cmp::Ordering::Equal => format!("Par {par}, right on par"),
+                std::cmp::Ordering::Greater => {
+                    format!("Par {par}, {} over", self.stats.moves - par)
+                }
+            });
+        }
+        lines.push(format!("Pushes: {}", self.stats.pushes));
+        lines.push(format!("Undos: {}", self.stats.undos));
+        lines.push(format!("Time: {}", format_time(self.seconds)));
+        if self.new_best {
+            lines.push("New personal best!".to_string());
+        } else if let Some(best) = self.best {
+            lines.push(format!(
+                "Best: {} moves in {}",
+                best.moves,
+                format_time(best.seconds)
+            ));
+        }
+        lines
+    }
+}
+
+/// The last level of a pack leads to the pack summary instead of a next level
+fn all_buttons(last_level: bool) -> [LevelCompleteButton; 4] {
+    [
+        if last_level {
+            LevelCompleteButton::FinishPack
+        } else {
+            LevelCompleteButton::NextLevel
+        },
+        LevelCompleteButton::Replay,
+        LevelCompleteButton::WatchReplay,
+        LevelCompleteButton::LevelSelect,
+    ]
+}
+
+#[derive(Component, Copy, Clone, PartialEq)]
+enum LevelCompleteButton {
+    NextLevel,
+    FinishPack,
+    Replay,
+    WatchReplay,
+    LevelSelect,
+}
+
+impl From<LevelCompleteButton> for String {
+    fn from(value: LevelCompleteButton) -> Self {
+        match value {
+            LevelCompleteButton::NextLevel => "Next Level",
+            LevelCompleteButton::FinishPack => "Finish Pack",
+            LevelCompleteButton::Replay => "Replay Level",
+            LevelCompleteButton::WatchReplay => "Watch Replay",
+            LevelCompleteButton::LevelSelect => "Level Select",
+        }
+        .to_string()
+    }
+}
+
+fn spawn_summary(
+    mut cmds: Commands,
+    theme: Res<ActiveTheme>,
+    summary: Res<Summary>,
+    current_level: Res<CurrentLevel>,
+    mut selected_button: ResMut<SelectedButton>,
+) {
+    **selected_button = 0;
+    let button_texture = theme.button.clone_weak();
+    let button_style = Style {
+        width: Val::Px(150.0),
+        height: Val::Px(50.0),
+        margin: UiRect {
+            top: Val::Px(6.),
+            bottom: Val::Px(6.),
+            ..default()
+        },
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        border: UiRect::all(Val::Px(2.)),
+        ..default()
+    };
+    let text_style = TextStyle {
+        font_size: 24.,
+        color: Color::BLACK,
+        ..default()
+    };
+
+    let parent = cmds
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::ANTIQUE_WHITE.with_a(0.85).into(),
+                ..default()
+            },
+            DependOnState::single(CURRENT_STATE),
+            Name::new("Level Complete"),
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    format!("Level {} Complete", **current_level + 1),
+                    TextStyle {
+                        font_size: 40.,
+                        ..text_style.clone()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(10.)),
+                    ..default()
+                }),
+            );
+            for line in summary.lines() {
+                parent.spawn(TextBundle::from_section(line, text_style.clone()));
+            }
+        })
+        .id();
+    for button in all_buttons(summary.last_level).iter() {
+        cmds.add(NineSliceButtonText {
+            button: *button,
+            style: button_style.clone(),
+            texture: button_texture.clone_weak(),
+            parent,
+        });
+    }
+}
+
+#[derive(Event, Deref, DerefMut)]
+struct InteractionEvent(pub LevelCompleteButton);
+
+fn handle_interaction(
+    mut events: EventReader<InteractionEvent>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut playback: ResMut<ReplayPlayback>,
+    last_replay: Res<LastReplay>,
+) {
+    for ev in events.read() {
+        match **ev {
+            LevelCompleteButton::NextLevel => {
+                current_level.0 += 1;
+                game_state.set(GameState::LevelTransition);
+            }
+            LevelCompleteButton::FinishPack => game_state.set(GameState::PackComplete),
+            LevelCompleteButton::Replay => game_state.set(GameState::LevelTransition),
+            LevelCompleteButton::WatchReplay => {
+                playback.watch(last_replay.0.clone());
+                game_state.set(GameState::LevelTransition);
+            }
+            LevelCompleteButton::LevelSelect => game_state.set(GameState::LevelSelect),
+        }
+    }
+}
+
+fn handle_buttons(
+    buttons: Query<(&LevelCompleteButton, &Interaction), Changed<Interaction>>,
+    mut event_writer: EventWriter<InteractionEvent>,
+) {
+    buttons.iter().for_each(|button| {
+        if let (button, Interaction::Pressed) = button {
+            event_writer.send(InteractionEvent(*button));
+        }
+    });
+}
+
+#[derive(Resource, Deref, DerefMut, Default)]
+struct SelectedButton(pub usize);
+
+fn ui_navigation(
+    navigation_actions: Query<&ActionState<SokobanActions>>,
+    mut selected_button: ResMut<SelectedButton>,
+    mut event_writer: EventWriter<InteractionEvent>,
+    summary: Res<Summary>,
+) {
+    let Ok(navigation_actions) = navigation_actions.get_single() else {
+        return;
+    };
+    let all_buttons = all_buttons(summary.last_level);
+    if navigation_actions.just_pressed(SokobanActions::UiNavUp) {
+        selected_button.0 = (**selected_button + all_buttons.len() - 1) % all_buttons.len();
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavDown) {
+        selected_button.0 = (**selected_button + 1) % all_buttons.len();
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavSelect) {
+        event_writer.send(InteractionEvent(all_buttons[**selected_button]));
+    }
+    if navigation_actions.just_pressed(SokobanActions::Escape) {
+        event_writer.send(InteractionEvent(LevelCompleteButton::LevelSelect));
+    }
+}
+
+fn render_selected_border(
+    selected_button: Res<SelectedButton>,
+    summary: Res<Summary>,
+    mut buttons: Query<(&LevelCompleteButton, &mut BorderColor)>,
+) {
+    for (button, mut border_color) in buttons.iter_mut() {
+        if *button == all_buttons(summary.last_level)[**selected_button] {
+            *border_color = BorderColor(Color::RED);
+        } else {
+            *border_color = BorderColor(Color::NONE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(moves: usize, seconds: f32) -> LevelResult {
+        LevelResult {
+            moves,
+            pushes: 0,
+            seconds,
+        }
+    }
+
+    fn summary(moves: usize, par: Option<u32>) -> Summary {
+        Summary {
+            stats: RecordingSummary {
+                moves,
+                pushes: 3,
+                undos: 1,
+            },
+            seconds: 75.5,
+            par,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn fewer_moves_beat_a_faster_time() {
+        assert!(result(10, 60.).beats(&result(11, 5.)));
+        assert!(!result(11, 5.).beats(&result(10, 60.)));
+    }
+
+    #[test]
+    fn time_breaks_ties() {
+        assert!(result(10, 5.).beats(&result(10, 6.)));
+        assert!(!result(10, 6.).beats(&result(10, 5.)));
+        assert!(!result(10, 5.).beats(&result(10, 5.)));
+    }
+
+    #[test]
+    fn lines_without_par() {
+        assert_eq!(
+            summary(12, None).lines(),
+            ["Moves: 12", "Pushes: 3", "Undos: 1", "Time: 1:15.5"]
+        );
+    }
+
+    #[test]
+    fn lines_compare_to_par() {
+        assert_eq!(summary(8, Some(10)).lines()[1], "Par 10, 2 under!");
+        assert_eq!(summary(10, Some(10)).lines()[1], "Par 10, right on par");
+        assert_eq!(summary(13, Some(10)).lines()[1], "Par 10, 3 over");
+    }
+
+    #[test]
+    fn lines_show_best() {
+        let mut new_best = summary(12, None);
+        new_best.new_best = true;
+        new_best.best = Some(result(20, 10.));
+        assert_eq!(new_best.lines().last().unwrap(), "New personal best!");
+
+        let mut old_best = summary(12, None);
+        old_best.best = Some(result(9, 42.));
+        assert_eq!(old_best.lines().last().unwrap(), "Best: 9 moves in 0:42.0");
+    }
+}
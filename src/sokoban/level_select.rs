@@ -4,8 +4,9 @@ use leafwing_input_manager::prelude::ActionState;
 use super::{
     cleanup::DependOnState,
     level::{LevelCollection, Levels},
+    theme::ActiveTheme,
     ui::NineSliceButtonText,
-    AssetsCollection, GameState, SokobanActions,
+    GameState, SokobanActions,
 };
 
 pub struct LevelSelectPlugin;
@@ -30,6 +31,17 @@ impl Plugin for LevelSelectPlugin {
 #[reflect(Resource)]
 pub struct CurrentLevel(pub usize);
 
+impl CurrentLevel {
+    /// Whether a level follows this one in a pack of `level_count` levels
+    pub fn has_next(&self, level_count: usize) -> bool {
+        self.0 + 1 < level_count
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.0 > 0
+    }
+}
+
 #[derive(Component, Deref, DerefMut, Clone)]
 struct LevelButton(pub usize);
 
@@ -110,9 +122,9 @@ fn spawn_level_select(
     mut cmds: Commands,
     level_assets: Res<LevelCollection>,
     levels: Res<Assets<Levels>>,
-    assets: Res<AssetsCollection>,
+    theme: Res<ActiveTheme>,
 ) {
-    let button_texture = assets.button.clone_weak();
+    let button_texture = theme.button.clone_weak();
     let button_style = Style {
         width: Val::Px(75.0),
         height: Val::Px(75.0),
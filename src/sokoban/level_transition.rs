@@ -1,115 +1,255 @@
 use std::time::Duration;
 
 use bevy::prelude::*;
+use bevy_tweening::{lens::UiBackgroundColorLens, Animator, EaseFunction, Tween, TweenCompleted};
+use leafwing_input_manager::prelude::ActionState;
 
 use super::{
     cleanup::DependOnState,
     event_scheduler::{EventScheduler, EventSchedulerPlugin},
+    level::{Level, LevelCollection, LevelRoot, Levels},
     level_select::CurrentLevel,
-    GameState,
+    GameState, SokobanActions,
 };
 
 const CURRENT_STATE: GameState = GameState::LevelTransition;
+const FADE: Duration = Duration::from_millis(400);
+/// How long the level card waits for input before continuing on its own
+const CARD_TIMEOUT: Duration = Duration::from_secs(4);
+/// `user_data` of the tween that finishes fading in the new level
+const FADE_IN_COMPLETED: u64 = 2;
+const CURTAIN_COLOR: Color = Color::ANTIQUE_WHITE;
 
 pub struct LevelTransitionPlugin;
 
 impl Plugin for LevelTransitionPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EventSchedulerPlugin::<LevelTransitionEvent>::default())
-            .add_systems(OnEnter(CURRENT_STATE), spawn_level_card)
-            .add_systems(Update, transition.run_if(in_state(CURRENT_STATE)));
+            .init_resource::<TransitionPhase>()
+            .add_systems(OnEnter(CURRENT_STATE), fade_out)
+            .add_systems(
+                OnTransition {
+                    from: CURRENT_STATE,
+                    to: GameState::Play,
+                },
+                fade_in,
+            )
+            .add_systems(
+                Update,
+                (transition, wait_for_input)
+                    .chain()
+                    .run_if(in_state(CURRENT_STATE)),
+            )
+            .add_systems(Update, despawn_curtain.run_if(on_event::<TweenCompleted>()));
     }
 }
 
+/// Covers the screen while the levels are swapped
 #[derive(Component)]
-struct LevelCard;
+struct Curtain;
 
 #[derive(Event)]
 enum LevelTransitionEvent {
-    End,
+    FadedOut,
+    Timeout,
+}
+
+#[derive(Resource, Default, PartialEq)]
+enum TransitionPhase {
+    #[default]
+    FadingOut,
+    Card,
 }
 
-fn spawn_level_card(
+fn fade_out(
     mut cmds: Commands,
-    current_level: Res<CurrentLevel>,
+    level_query: Query<(), With<LevelRoot>>,
+    mut phase: ResMut<TransitionPhase>,
     mut level_transition_scheduler: ResMut<EventScheduler<LevelTransitionEvent>>,
 ) {
+    *phase = TransitionPhase::FadingOut;
+    level_transition_scheduler.clear();
+
+    // Coming from a menu there is no level to fade out
+    let fade = if level_query.is_empty() {
+        Duration::ZERO
+    } else {
+        FADE
+    };
     cmds.spawn((
         NodeBundle {
             style: Style {
+                position_type: PositionType::Absolute,
                 width: Val::Percent(100.),
                 height: Val::Percent(100.),
                 align_items: AlignItems::Center,
-                align_content: AlignContent::Center,
-                margin: UiRect::all(Val::Auto),
                 justify_content: JustifyContent::Center,
                 flex_direction: FlexDirection::Column,
                 ..default()
             },
+            background_color: CURTAIN_COLOR.with_a(0.).into(),
+            z_index: ZIndex::Global(100),
             ..default()
         },
-        DependOnState::single(CURRENT_STATE),
-    ))
-    .with_children(|parent| {
-        parent.spawn(TextBundle {
-            style: Style {
-                width: Val::Auto,
-                height: Val::Px(65.),
-                margin: UiRect {
-                    top: Val::Px(10.),
-                    bottom: Val::Px(10.),
-                    ..default()
-                },
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                border: UiRect::all(Val::Px(4.)),
-                ..default()
+        Animator::new(Tween::new(
+            EaseFunction::QuadraticIn,
+            fade.max(Duration::from_millis(1)),
+            UiBackgroundColorLens {
+                start: CURTAIN_COLOR.with_a(0.),
+                end: CURTAIN_COLOR,
             },
-            text: Text::from_section(
-                format!("Level {}", **current_level),
-                TextStyle {
-                    font_size: 48.,
-                    color: Color::BLACK,
-                    ..default()
-                },
-            ),
+        )),
+        Curtain,
+        DependOnState(vec![CURRENT_STATE, GameState::Play]),
+        Name::new("Curtain"),
+    ));
+    level_transition_scheduler.schedule(LevelTransitionEvent::FadedOut, fade);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn transition(
+    mut cmds: Commands,
+    mut level_transition: EventReader<LevelTransitionEvent>,
+    mut phase: ResMut<TransitionPhase>,
+    mut level_transition_scheduler: ResMut<EventScheduler<LevelTransitionEvent>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    depend_query: Query<(Entity, &DependOnState<GameState>)>,
+    curtain_query: Query<Entity, With<Curtain>>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+) {
+    for ev in level_transition.read() {
+        match ev {
+            LevelTransitionEvent::FadedOut => {
+                // The old level stayed around to be faded out, the new one is spawned
+                // when entering play
+                for (entity, states) in depend_query.iter() {
+                    if states.contains(&GameState::Play) && !curtain_query.contains(entity) {
+                        cmds.entity(entity).despawn_recursive();
+                    }
+                }
+                if let Ok(curtain) = curtain_query.get_single() {
+                    let level = levels
+                        .get(&level_collection.levels)
+                        .and_then(|levels| levels.get(**current_level));
+                    cmds.entity(curtain)
+                        .with_children(|parent| spawn_level_card(parent, **current_level, level));
+                }
+                *phase = TransitionPhase::Card;
+                level_transition_scheduler.schedule(LevelTransitionEvent::Timeout, CARD_TIMEOUT);
+            }
+            LevelTransitionEvent::Timeout => next_state.set(GameState::Play),
+        }
+    }
+}
+
+fn wait_for_input(
+    phase: Res<TransitionPhase>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    actions: Query<&ActionState<SokobanActions>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if *phase != TransitionPhase::Card {
+        return;
+    }
+    let select = actions
+        .get_single()
+        .is_ok_and(|actions| actions.just_pressed(SokobanActions::UiNavSelect));
+    if select
+        || keys.get_just_pressed().next().is_some()
+        || mouse_buttons.get_just_pressed().next().is_some()
+        || gamepad_buttons.get_just_pressed().next().is_some()
+    {
+        next_state.set(GameState::Play);
+    }
+}
+
+fn spawn_level_card(parent: &mut ChildBuilder, index: usize, level: Option<&Level>) {
+    let text_style = TextStyle {
+        font_size: 28.,
+        color: Color::BLACK,
+        ..default()
+    };
+    let margin = UiRect {
+        top: Val::Px(10.),
+        bottom: Val::Px(10.),
+        ..default()
+    };
+
+    parent.spawn(
+        TextBundle::from_section(
+            format!("Level {}", index + 1),
+            TextStyle {
+                font_size: 48.,
+                ..text_style.clone()
+            },
+        )
+        .with_style(Style {
+            margin,
             ..default()
-        });
-    })
-    .with_children(|parent| {
-        parent.spawn(TextBundle {
-            style: Style {
-                width: Val::Auto,
-                height: Val::Px(65.),
-                margin: UiRect {
-                    top: Val::Px(10.),
-                    bottom: Val::Px(10.),
-                    ..default()
+        }),
+    );
+    if let Some(name) = level
+        .map(|level| &level.name)
+        .filter(|name| !name.is_empty())
+    {
+        parent.spawn(
+            TextBundle::from_section(
+                name.clone(),
+                TextStyle {
+                    font_size: 36.,
+                    ..text_style.clone()
                 },
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                border: UiRect::all(Val::Px(4.)),
+            )
+            .with_style(Style {
+                margin,
                 ..default()
-            },
-            text: Text::from_section(
-                "Press any key to continue",
-                TextStyle {
-                    font_size: 28.,
-                    color: Color::BLACK,
+            }),
+        );
+    }
+    if let Some(par) = level.and_then(|level| level.par) {
+        parent.spawn(
+            TextBundle::from_section(format!("Par: {par} moves"), text_style.clone()).with_style(
+                Style {
+                    margin,
                     ..default()
                 },
             ),
+        );
+    }
+    parent.spawn(
+        TextBundle::from_section("Press any key to continue", text_style).with_style(Style {
+            margin,
             ..default()
-        });
-    });
-    level_transition_scheduler.schedule(LevelTransitionEvent::End, Duration::from_millis(50));
+        }),
+    );
 }
 
-fn transition(
-    level_transition: EventReader<LevelTransitionEvent>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
-    if !level_transition.is_empty() {
-        next_state.set(GameState::Play)
+fn fade_in(mut cmds: Commands, curtain_query: Query<Entity, With<Curtain>>) {
+    for curtain in curtain_query.iter() {
+        cmds.entity(curtain)
+            .despawn_descendants()
+            .insert(Animator::new(
+                Tween::new(
+                    EaseFunction::QuadraticOut,
+                    FADE,
+                    UiBackgroundColorLens {
+                        start: CURTAIN_COLOR,
+                        end: CURTAIN_COLOR.with_a(0.),
+                    },
+                )
+                .with_completed_event(FADE_IN_COMPLETED),
+            ));
+    }
+}
+
+fn despawn_curtain(mut cmds: Commands, mut completed: EventReader<TweenCompleted>) {
+    for ev in completed.read() {
+        if ev.user_data == FADE_IN_COMPLETED {
+            cmds.entity(ev.entity).despawn_recursive();
+        }
     }
 }
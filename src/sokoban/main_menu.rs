@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 use bevy_nine_slice_ui::NineSliceTexture;
 
-use super::{cleanup::DependOnState, ui::NineSliceButtonText, AssetsCollection, GameState};
+use super::{
+    cleanup::DependOnState, settings::SettingsReturn, theme::ActiveTheme, ui::NineSliceButtonText,
+    GameState,
+};
 
 pub struct MainMenuPlugin;
 
@@ -18,6 +21,8 @@ impl Plugin for MainMenuPlugin {
 #[derive(Component, Clone)]
 enum MainMenuButton {
     Play,
+    Controls,
+    Settings,
     #[cfg(not(target_family = "wasm"))]
     Exit,
 }
@@ -26,6 +31,8 @@ impl From<MainMenuButton> for String {
     fn from(value: MainMenuButton) -> Self {
         match value {
             MainMenuButton::Play => "Play",
+            MainMenuButton::Controls => "Controls",
+            MainMenuButton::Settings => "Settings",
             MainMenuButton::Exit => "Exit",
         }
         .to_string()
@@ -34,19 +41,25 @@ impl From<MainMenuButton> for String {
 
 fn handle_buttons(
     mut game_state: ResMut<NextState<GameState>>,
+    mut settings_return: ResMut<SettingsReturn>,
     buttons: Query<(&MainMenuButton, &Interaction), Changed<Interaction>>,
     #[cfg(not(target_family = "wasm"))] mut events: EventWriter<bevy::app::AppExit>,
 ) {
     buttons.iter().for_each(|button| match button {
         (MainMenuButton::Play, Interaction::Pressed) => game_state.set(GameState::LevelSelect),
+        (MainMenuButton::Controls, Interaction::Pressed) => game_state.set(GameState::Controls),
+        (MainMenuButton::Settings, Interaction::Pressed) => {
+            **settings_return = GameState::MainMenu;
+            game_state.set(GameState::Settings);
+        }
         #[cfg(not(target_family = "wasm"))]
         (MainMenuButton::Exit, Interaction::Pressed) => events.send(bevy::app::AppExit),
         _ => {}
     });
 }
 
-fn spawn_main_menu(mut cmds: Commands, assets: Res<AssetsCollection>) {
-    let button_texture = assets.button.clone_weak();
+fn spawn_main_menu(mut cmds: Commands, theme: Res<ActiveTheme>) {
+    let button_texture = theme.button.clone_weak();
     let button_style = Style {
         width: Val::Px(150.0),
         height: Val::Px(65.0),
@@ -85,6 +98,18 @@ fn spawn_main_menu(mut cmds: Commands, assets: Res<AssetsCollection>) {
         texture: button_texture.clone_weak(),
         parent,
     });
+    cmds.add(NineSliceButtonText {
+        button: MainMenuButton::Controls,
+        style: button_style.clone(),
+        texture: button_texture.clone_weak(),
+        parent,
+    });
+    cmds.add(NineSliceButtonText {
+        button: MainMenuButton::Settings,
+        style: button_style.clone(),
+        texture: button_texture.clone_weak(),
+        parent,
+    });
     #[cfg(not(target_family = "wasm"))]
     cmds.add(NineSliceButtonText {
         button: MainMenuButton::Exit,
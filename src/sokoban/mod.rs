@@ -1,42 +1,80 @@
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::{AssetCollection, LoadingState, LoadingStateAppExt};
-use bevy_ecs_tilemap::{prelude::TilemapGridSize, tiles::TilePos, TilemapPlugin};
+use bevy_ecs_tilemap::{tiles::TilePos, TilemapPlugin};
 use bevy_nine_slice_ui::NineSlicePlugin;
-use bevy_pile::tilemap::tile_to_world_pos;
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::sokoban::momentum::Momentum;
 
 use self::{
+    animation::AnimationPlugin,
     audio::{AudioCollection, GameAudioPlugin},
+    auto_walk::AutoWalkPlugin,
+    autotile::AutotilePlugin,
+    camera::CameraPlugin,
     cleanup::cleanup_on_state_change,
     collision::CollisionPlugin,
+    controls::{Controls, ControlsPlugin},
     entity::CommandHistoryPlugin,
+    gamepad::GamepadPlugin,
+    grid::GridPlugin,
     history::{HandleHistoryEvents, History, HistoryComponentPlugin, HistoryEvent, HistoryPlugin},
+    inventory::{Inventory, InventoryPlugin, Key, Lock},
+    laser::LaserPlugin,
     level::{LevelCollection, LevelPlugin},
+    level_complete::LevelCompletePlugin,
     level_select::LevelSelectPlugin,
     level_transition::LevelTransitionPlugin,
     main_menu::MainMenuPlugin,
     momentum::MomentumPlugin,
+    motion::{Glide, MotionPlugin},
+    music::{MusicCollection, MusicPlugin},
+    pack_complete::PackCompletePlugin,
     pause_menu::PauseMenuPlugin,
-    player::PlayerPlugin,
-    tile_behaviour::TileBehaviourPlugin,
+    player::{InputBuffer, PlayerPlugin},
+    push_planner::PushPlannerPlugin,
+    replay::{Recording, ReplayPlayback, ReplayPlugin, ReplayStep},
+    settings::SettingsPlugin,
+    simulation::SimulationPlugin,
+    theme::{ThemeCollection, ThemePlugin},
+    tile_behaviour::{Lamp, Switch, TileBehaviourPlugin},
 };
 
+pub mod animation;
 pub mod audio;
+pub mod auto_walk;
+pub mod autotile;
 pub mod ball;
+pub mod camera;
 pub mod cleanup;
 pub mod collision;
+pub mod config;
+pub mod controls;
+pub mod crates;
 pub mod entity;
 pub mod event_scheduler;
+pub mod gamepad;
+pub mod grid;
 pub mod history;
+pub mod inventory;
+pub mod laser;
 pub mod level;
+pub mod level_complete;
 pub mod level_select;
 pub mod level_transition;
 pub mod main_menu;
 pub mod momentum;
+pub mod motion;
+pub mod music;
+pub mod pack_complete;
 pub mod pause_menu;
 pub mod player;
+pub mod push_planner;
+pub mod replay;
+pub mod settings;
+pub mod simulation;
+pub mod theme;
 pub mod tile_behaviour;
 pub mod ui;
 pub mod util;
@@ -48,7 +86,14 @@ impl Plugin for SokobanPlugin {
         app.add_plugins((
             PlayerPlugin,
             HistoryPlugin,
-            HistoryComponentPlugin::<Pos>::default(),
+            (
+                HistoryComponentPlugin::<Pos>::default(),
+                HistoryComponentPlugin::<Inventory>::default(),
+                HistoryComponentPlugin::<Key>::default(),
+                HistoryComponentPlugin::<Lock>::default(),
+                HistoryComponentPlugin::<Lamp>::default(),
+                HistoryComponentPlugin::<Switch>::default(),
+            ),
             InputManagerPlugin::<SokobanActions>::default(),
             MomentumPlugin,
             CollisionPlugin,
@@ -59,8 +104,31 @@ impl Plugin for SokobanPlugin {
             LevelTransitionPlugin,
             TileBehaviourPlugin,
             CommandHistoryPlugin,
-            TilemapPlugin,
-            (GameAudioPlugin, NineSlicePlugin::default()),
+            (
+                TilemapPlugin,
+                NineSlicePlugin::default(),
+                CameraPlugin,
+                GridPlugin,
+                AutotilePlugin,
+                AnimationPlugin,
+            ),
+            (
+                GameAudioPlugin,
+                InventoryPlugin,
+                LaserPlugin,
+                SimulationPlugin,
+                ControlsPlugin,
+                GamepadPlugin,
+                AutoWalkPlugin,
+                PushPlannerPlugin,
+                MusicPlugin,
+                SettingsPlugin,
+                MotionPlugin,
+                ReplayPlugin,
+                LevelCompletePlugin,
+                PackCompletePlugin,
+                ThemePlugin,
+            ),
         ))
         .add_state::<GameState>()
         .add_loading_state(
@@ -69,6 +137,8 @@ impl Plugin for SokobanPlugin {
         .add_collection_to_loading_state::<_, AssetsCollection>(GameState::AssetLoading)
         .add_collection_to_loading_state::<_, LevelCollection>(GameState::AssetLoading)
         .add_collection_to_loading_state::<_, AudioCollection>(GameState::AssetLoading)
+        .add_collection_to_loading_state::<_, MusicCollection>(GameState::AssetLoading)
+        .add_collection_to_loading_state::<_, ThemeCollection>(GameState::AssetLoading)
         .register_type::<Pos>()
         .register_type::<Dir>()
         .register_type::<History<Pos>>()
@@ -76,7 +146,6 @@ impl Plugin for SokobanPlugin {
         .register_type::<AssetsCollection>()
         .add_event::<SokobanEvent>()
         .add_systems(Startup, setup)
-        .insert_resource(Time::<Fixed>::from_hz(8.))
         .add_systems(
             Update,
             (
@@ -89,40 +158,44 @@ impl Plugin for SokobanPlugin {
         .add_systems(
             StateTransition,
             cleanup_on_state_change::<GameState>.before(apply_state_transition::<GameState>),
-        )
-        .add_systems(PostUpdate, copy_pos_to_transform);
+        );
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States, Deserialize)]
 pub enum GameState {
     #[default]
     AssetLoading,
     MainMenu,
     LevelSelect,
+    Controls,
+    Settings,
     LevelTransition,
     Play,
     Pause,
+    LevelComplete,
+    PackComplete,
 }
 
+/// States the entities of a loaded level live through, they are despawned once a
+/// level transition has faded out
+pub const LEVEL_STATES: [GameState; 5] = [
+    GameState::LevelTransition,
+    GameState::Play,
+    GameState::Pause,
+    GameState::Settings,
+    GameState::LevelComplete,
+];
+
 #[derive(Resource, Reflect, Default, AssetCollection, Debug)]
 #[reflect(Resource)]
 pub struct AssetsCollection {
-    #[asset(path = "tiles.png")]
-    pub tiles: Handle<Image>,
-    #[asset(path = "wall.png")]
-    pub wall_tiles: Handle<Image>,
-    #[asset(path = "player.png")]
-    pub player: Handle<Image>,
-    #[asset(path = "ball.png")]
-    pub ball: Handle<Image>,
-    #[asset(path = "goal.png")]
-    pub goal: Handle<Image>,
-    #[asset(path = "button.png")]
-    pub button: Handle<Image>,
+    #[asset(texture_atlas(tile_size_x = 12., tile_size_y = 12., columns = 15, rows = 1))]
+    #[asset(path = "gamepad_glyphs.png")]
+    pub gamepad_glyphs: Handle<TextureAtlas>,
 }
 
-#[derive(Actionlike, Clone, Copy, Hash, Debug, PartialEq, Eq, Reflect)]
+#[derive(Actionlike, Clone, Copy, Hash, Debug, PartialEq, Eq, Reflect, Serialize, Deserialize)]
 pub enum SokobanActions {
     Undo,
     Escape,
@@ -132,62 +205,66 @@ pub enum SokobanActions {
     UiNavDown,
     UiNavLeft,
     UiNavSelect,
+    FastForward,
+    ChangeSpeed,
+    ToggleInstant,
+    ZoomIn,
+    ZoomOut,
+    ResetView,
+    Pan,
 }
 
-fn sokoban_actions() -> InputMap<SokobanActions> {
-    use SokobanActions::*;
-    let mut input_map = InputMap::default();
-
-    input_map.insert(KeyCode::E, Undo);
-    input_map.insert_many_to_one(vec![KeyCode::Escape, KeyCode::Q], Escape);
-    input_map.insert(KeyCode::R, Reset);
-
-    input_map.insert(KeyCode::W, UiNavUp);
-    input_map.insert(KeyCode::D, UiNavRight);
-    input_map.insert(KeyCode::S, UiNavDown);
-    input_map.insert(KeyCode::A, UiNavLeft);
-    input_map.insert(KeyCode::F, UiNavSelect);
-
-    input_map
-}
-
-fn setup(mut cmds: Commands) {
+fn setup(mut cmds: Commands, controls: Res<Controls>) {
     cmds.spawn((
         (InputManagerBundle::<SokobanActions> {
-            input_map: sokoban_actions(),
+            input_map: controls.sokoban_input_map(),
             ..default()
         },),
         Name::new("SokobanActions"),
     ));
 }
 
-#[derive(Debug, Clone, Event)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Event)]
 pub enum SokobanEvent {
     PlayerMoved,
     PlayerPush,
     BallPush,
     BallHitWall,
     EntityInVoid,
+    RubberBounce,
+    SandStop,
+    LampToggled,
+    LevelComplete,
 }
 
 fn undo(
     actions: Query<&ActionState<SokobanActions>>,
     mut history_events: EventWriter<HistoryEvent>,
     mut momentum_query: Query<&mut Momentum>,
+    mut input_buffer: ResMut<InputBuffer>,
+    mut recording: ResMut<Recording>,
+    playback: Res<ReplayPlayback>,
 ) {
     let Ok(actions) = actions.get_single() else {
         return;
     };
+    if playback.is_playing() {
+        return;
+    }
     if actions.just_pressed(SokobanActions::Undo) {
+        recording.record(ReplayStep::Undo);
         history_events.send(HistoryEvent::Rewind);
         for mut momentum in momentum_query.iter_mut() {
             momentum.take();
         }
+        input_buffer.clear();
     } else if actions.just_pressed(SokobanActions::Reset) {
+        recording.record(ReplayStep::Reset);
         history_events.send(HistoryEvent::Reset);
         for mut momentum in momentum_query.iter_mut() {
             momentum.take();
         }
+        input_buffer.clear();
     }
 }
 
@@ -236,16 +313,7 @@ impl From<&Pos> for IVec2 {
     }
 }
 
-pub fn copy_pos_to_transform(mut query: Query<(&Pos, &mut Transform), Changed<Pos>>) {
-    for (pos, mut transform) in query.iter_mut() {
-        let new = tile_to_world_pos(pos, &TilemapGridSize { x: 8., y: 8. })
-            .extend(transform.translation.z);
-
-        transform.translation = new;
-    }
-}
-
-#[derive(Debug, Copy, Clone, Reflect)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Reflect)]
 pub enum Dir {
     Up,
     Right,
@@ -254,6 +322,9 @@ pub enum Dir {
 }
 
 impl Dir {
+    /// Every direction, clockwise starting at up
+    pub const ALL: [Dir; 4] = [Dir::Up, Dir::Right, Dir::Down, Dir::Left];
+
     pub fn opposite(&self) -> Dir {
         use Dir::*;
         match self {
@@ -286,6 +357,7 @@ pub enum SokobanBlock {
 pub struct DynamicBundle {
     momentum: Momentum,
     block: SokobanBlock,
+    glide: Glide,
 }
 
 impl Default for DynamicBundle {
@@ -293,6 +365,7 @@ impl Default for DynamicBundle {
         Self {
             momentum: Momentum::default(),
             block: SokobanBlock::Dynamic,
+            glide: Glide::default(),
         }
     }
 }
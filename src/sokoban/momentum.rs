@@ -1,12 +1,14 @@
 use std::time::Duration;
 
-use bevy::{log, prelude::*};
+use bevy::{prelude::*, utils::HashMap};
 
 use super::{
-    collision::{CollisionMap, CollisionResult},
+    collision::CollisionMap,
+    crates::Crate,
     history::HandleHistoryEvents,
     player::{player_movement, Player},
-    Dir, GameState, Pos,
+    tile_behaviour::Ice,
+    Dir, GameState, Pos, SokobanBlock, SokobanEvent,
 };
 
 pub struct MomentumPlugin;
@@ -30,64 +32,214 @@ impl Plugin for MomentumPlugin {
 #[derive(Default, Component, Copy, Clone, Deref, DerefMut, Reflect)]
 pub struct Momentum(pub Option<Dir>);
 
+/// Resolves collisions between everything that is currently moving.
+///
+/// Rules, applied to a snapshot so the outcome never depends on entity order:
+/// - Bodies touching along an axis form a line. Momentum passes through a line
+///   ala newtons cradle: if `k` members move forward, the `k` front-most members
+///   leave moving forward and the rest come to rest. Backward movers exit at the
+///   rear the same way, so two movers meeting head-on both bounce back.
+/// - Momentum that would exit a line into a wall or another obstacle is lost.
+/// - Bodies moving across a line, resting crates and crates that can't move as a
+///   whole are obstacles for that line.
+/// - A resting body hit from two axes at once absorbs both and stays at rest.
+/// - Movers that would enter the same cell, or a cell that isn't being vacated in
+///   their direction, stop.
 pub fn transfer_momentum(
-    mut momentum_query: Query<(Entity, &mut Pos, &mut Momentum)>,
+    mut momentum_query: Query<(Entity, &Pos, &mut Momentum, Has<Crate>, Has<Player>)>,
     collision: Res<CollisionMap>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
 ) {
-    // Get all entities that are moving right now
-    let has_momentum: Vec<(Entity, Pos, Dir)> = momentum_query
+    let (entities, bodies): (Vec<Entity>, Vec<Body>) = momentum_query
         .iter()
-        .filter_map(|(entity, pos, momentum)| momentum.map(|direction| (entity, *pos, direction)))
+        .map(|(entity, pos, momentum, is_crate, _)| {
+            (
+                entity,
+                Body {
+                    pos: IVec2::from(pos),
+                    momentum: **momentum,
+                    is_crate,
+                },
+            )
+        })
+        .unzip();
+
+    let resolved = resolve_momentum(&bodies, |pos| {
+        !collision.in_bounds(pos) || matches!(collision.get(pos), Some((_, SokobanBlock::Static)))
+    });
+
+    let mut ball_pushed = false;
+    let mut ball_stopped = false;
+    for (entity, resolved) in entities.into_iter().zip(resolved) {
+        let (_, _, mut momentum, _, is_player) = momentum_query
+            .get_mut(entity)
+            .expect("Entity was part of the query above");
+        if **momentum != resolved {
+            if !is_player {
+                ball_pushed |= momentum.is_none();
+                ball_stopped |= resolved.is_none();
+            }
+            **momentum = resolved;
+        }
+    }
+    // A ball stopping next to the one it handed its momentum to didn't hit a wall
+    if ball_pushed {
+        sokoban_events.send(SokobanEvent::BallPush);
+    } else if ball_stopped {
+        sokoban_events.send(SokobanEvent::BallHitWall);
+    }
+}
+
+/// Snapshot of a dynamic entity used to resolve collisions
+#[derive(Debug, Clone, Copy)]
+pub struct Body {
+    pub pos: IVec2,
+    pub momentum: Option<Dir>,
+    pub is_crate: bool,
+}
+
+/// Returns the momentum every body has after resolving collisions for one step.
+/// `is_solid` should be true for static blocks and positions out of bounds.
+pub fn resolve_momentum(bodies: &[Body], is_solid: impl Fn(IVec2) -> bool) -> Vec<Option<Dir>> {
+    let occupied: HashMap<IVec2, usize> = bodies
+        .iter()
+        .enumerate()
+        .map(|(idx, body)| (body.pos, idx))
         .collect();
-    for (entity, pos, direction) in has_momentum.iter() {
-        // Entities that get pushed including the pusher
-        let push = collision.push_collision(IVec2::from(*pos), *direction);
-        match push {
-            CollisionResult::Push(push) => {
-                // Transfer pushers momentum ala newtons cradle
-
-                // Find entity without momentum
-                let mut latest_without_momentum = None;
-                for e in push.iter() {
-                    if momentum_query
-                        .get_component::<Momentum>(*e)
-                        .expect("Dynamic objects have a momentum component")
-                        .is_none()
-                    {
-                        latest_without_momentum.replace((*e, direction));
-                    }
+
+    // Crates only move if everything in front of them moves along, otherwise they stall
+    let momentum: Vec<Option<Dir>> = bodies
+        .iter()
+        .map(|body| {
+            let dir = body.momentum?;
+            if !body.is_crate {
+                return Some(dir);
+            }
+            let mut ahead = body.pos + IVec2::from(dir);
+            while let Some(&idx) = occupied.get(&ahead) {
+                if bodies[idx].momentum != Some(dir) {
+                    return None;
                 }
+                ahead += IVec2::from(dir);
+            }
+            Some(dir)
+        })
+        .collect();
 
-                // Transfer momentum
-                if let Some((transfer, momentum)) = latest_without_momentum {
-                    let [(_, _, mut tm), (_, _, mut em)] =
-                        momentum_query.get_many_mut([transfer, *entity]).expect(
-                            "Both entities are guaranteed to have a 
-                            position and momentum by above invariants",
-                        );
-                    tm.replace(*momentum);
-                    em.take();
+    // Momentum handed out to each body by the lines it is part of
+    let mut handed_out: Vec<Vec<Option<Dir>>> = vec![Vec::new(); bodies.len()];
+    for axis in [Dir::Right, Dir::Up] {
+        let is_member = |idx: usize| match momentum[idx] {
+            None => !bodies[idx].is_crate,
+            Some(dir) => dir == axis || dir == axis.opposite(),
+        };
+        let step = IVec2::from(axis);
+        let blocked = |pos: IVec2| is_solid(pos) || occupied.contains_key(&pos);
+
+        let mut visited = vec![false; bodies.len()];
+        for start in 0..bodies.len() {
+            if visited[start] || !is_member(start) {
+                continue;
+            }
+
+            let mut rear = start;
+            while let Some(&prev) = occupied.get(&(bodies[rear].pos - step)) {
+                if !is_member(prev) {
+                    break;
                 }
+                rear = prev;
             }
-            CollisionResult::Wall => {
-                // Stoppable force meets immovable object
-                momentum_query
-                    .get_component_mut::<Momentum>(*entity)
-                    .expect("Dynamic objects have a momentum component")
-                    .take();
+            let mut line = vec![rear];
+            while let Some(&next) = occupied.get(&(bodies[line[line.len() - 1]].pos + step)) {
+                if !is_member(next) {
+                    break;
+                }
+                line.push(next);
             }
-            CollisionResult::OutOfBounds => {
-                log::warn!("Entity {:?} out of bounds", entity);
+            line.iter().for_each(|idx| visited[*idx] = true);
+
+            let forward = line
+                .iter()
+                .filter(|idx| momentum[**idx] == Some(axis))
+                .count();
+            let backward = line
+                .iter()
+                .filter(|idx| momentum[**idx] == Some(axis.opposite()))
+                .count();
+            if forward + backward == 0 {
+                continue;
             }
+
+            let len = line.len();
+            let front_free = !blocked(bodies[line[len - 1]].pos + step);
+            let rear_free = !blocked(bodies[line[0]].pos - step);
+            for (k, idx) in line.iter().enumerate() {
+                let dir = if k >= len - forward && front_free {
+                    Some(axis)
+                } else if k < backward && rear_free {
+                    Some(axis.opposite())
+                } else {
+                    None
+                };
+                handed_out[*idx].push(dir);
+            }
+        }
+    }
+
+    let mut resolved: Vec<Option<Dir>> = handed_out
+        .iter()
+        .zip(momentum)
+        .map(|(handed_out, momentum)| {
+            if handed_out.is_empty() {
+                return momentum;
+            }
+            let mut dirs = handed_out.iter().flatten();
+            match (dirs.next(), dirs.next()) {
+                (Some(dir), None) => Some(*dir),
+                _ => None,
+            }
+        })
+        .collect();
+
+    // Stop movers that would end up in an occupied cell until nothing changes
+    loop {
+        let mut targets: HashMap<IVec2, usize> = HashMap::default();
+        for (body, dir) in bodies.iter().zip(resolved.iter()) {
+            if let Some(dir) = dir {
+                *targets.entry(body.pos + IVec2::from(*dir)).or_default() += 1;
+            }
+        }
+        let stop: Vec<usize> = (0..bodies.len())
+            .filter(|idx| {
+                let Some(dir) = resolved[*idx] else {
+                    return false;
+                };
+                let dest = bodies[*idx].pos + IVec2::from(dir);
+                is_solid(dest)
+                    || targets[&dest] > 1
+                    || occupied
+                        .get(&dest)
+                        .is_some_and(|other| resolved[*other] != Some(dir))
+            })
+            .collect();
+        if stop.is_empty() {
+            break;
         }
+        stop.into_iter().for_each(|idx| resolved[idx] = None);
     }
+
+    resolved
 }
 
-pub fn apply_momentum(mut momentum_query: Query<(&mut Pos, &mut Momentum, Option<&Player>)>) {
-    for (mut pos, mut momentum, player) in momentum_query.iter_mut() {
+pub fn apply_momentum(
+    mut momentum_query: Query<(&mut Pos, &mut Momentum, Has<Player>, Has<Crate>), Without<Ice>>,
+    ice_query: Query<&Pos, With<Ice>>,
+) {
+    for (mut pos, mut momentum, is_player, is_crate) in momentum_query.iter_mut() {
         if let Some(dir) = **momentum {
             pos.add_dir(dir);
-            if player.is_some() {
+            // Player only keeps sliding while standing on ice, crates never slide
+            if is_crate || (is_player && !ice_query.iter().any(|ice_pos| *ice_pos == *pos)) {
                 momentum.take();
             };
         }
@@ -108,3 +260,166 @@ impl Default for MomentumTimer {
         Self(Timer::new(Duration::from_millis(25), TimerMode::Once))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ball(x: i32, y: i32, momentum: Option<Dir>) -> Body {
+        Body {
+            pos: IVec2::new(x, y),
+            momentum,
+            is_crate: false,
+        }
+    }
+
+    fn crate_body(x: i32, y: i32, momentum: Option<Dir>) -> Body {
+        Body {
+            is_crate: true,
+            ..ball(x, y, momentum)
+        }
+    }
+
+    // Open 10x10 room surrounded by walls
+    fn room(pos: IVec2) -> bool {
+        pos.x < 0 || pos.y < 0 || pos.x >= 10 || pos.y >= 10
+    }
+
+    // Resolves every rotation of the input to make sure order doesn't matter
+    fn resolve(bodies: &[Body]) -> Vec<Option<Dir>> {
+        let expected = resolve_momentum(bodies, room);
+        for shift in 1..bodies.len() {
+            let mut rotated = bodies.to_vec();
+            rotated.rotate_left(shift);
+            let mut result = resolve_momentum(&rotated, room);
+            result.rotate_right(shift);
+            assert_eq!(result, expected, "Resolution depends on body order");
+        }
+        expected
+    }
+
+    #[test]
+    fn free_mover_keeps_momentum() {
+        assert_eq!(resolve(&[ball(1, 1, Some(Dir::Right))]), [Some(Dir::Right)]);
+    }
+
+    #[test]
+    fn mover_stops_at_wall() {
+        assert_eq!(resolve(&[ball(9, 1, Some(Dir::Right))]), [None]);
+    }
+
+    #[test]
+    fn momentum_moves_to_front_of_chain() {
+        let bodies = [
+            ball(1, 1, Some(Dir::Right)),
+            ball(2, 1, None),
+            ball(3, 1, None),
+        ];
+        assert_eq!(resolve(&bodies), [None, None, Some(Dir::Right)]);
+    }
+
+    #[test]
+    fn chain_against_wall_stops() {
+        let bodies = [ball(8, 1, Some(Dir::Right)), ball(9, 1, None)];
+        assert_eq!(resolve(&bodies), [None, None]);
+    }
+
+    #[test]
+    fn several_movers_in_chain_push_front_members() {
+        let bodies = [
+            ball(1, 1, Some(Dir::Right)),
+            ball(2, 1, Some(Dir::Right)),
+            ball(3, 1, None),
+        ];
+        assert_eq!(resolve(&bodies), [None, Some(Dir::Right), Some(Dir::Right)]);
+    }
+
+    #[test]
+    fn pushed_train_moves_together() {
+        let bodies = [
+            ball(1, 1, Some(Dir::Up)),
+            ball(1, 2, Some(Dir::Up)),
+            ball(1, 3, Some(Dir::Up)),
+        ];
+        assert_eq!(resolve(&bodies), [Some(Dir::Up); 3]);
+    }
+
+    #[test]
+    fn head_on_movers_bounce() {
+        let bodies = [ball(4, 1, Some(Dir::Right)), ball(5, 1, Some(Dir::Left))];
+        assert_eq!(resolve(&bodies), [Some(Dir::Left), Some(Dir::Right)]);
+    }
+
+    #[test]
+    fn head_on_through_resting_ball_bounces_ends() {
+        let bodies = [
+            ball(3, 1, Some(Dir::Right)),
+            ball(4, 1, None),
+            ball(5, 1, Some(Dir::Left)),
+        ];
+        assert_eq!(resolve(&bodies), [Some(Dir::Left), None, Some(Dir::Right)]);
+    }
+
+    #[test]
+    fn head_on_next_to_wall_only_bounces_free_side() {
+        let bodies = [ball(8, 1, Some(Dir::Right)), ball(9, 1, Some(Dir::Left))];
+        assert_eq!(resolve(&bodies), [Some(Dir::Left), None]);
+    }
+
+    #[test]
+    fn movers_entering_same_cell_stop() {
+        let bodies = [ball(3, 1, Some(Dir::Right)), ball(5, 1, Some(Dir::Left))];
+        assert_eq!(resolve(&bodies), [None, None]);
+
+        let bodies = [ball(3, 3, Some(Dir::Right)), ball(4, 4, Some(Dir::Down))];
+        assert_eq!(resolve(&bodies), [None, None]);
+    }
+
+    #[test]
+    fn crossing_mover_is_an_obstacle() {
+        let bodies = [ball(3, 3, Some(Dir::Right)), ball(4, 3, Some(Dir::Up))];
+        assert_eq!(resolve(&bodies), [None, Some(Dir::Up)]);
+    }
+
+    #[test]
+    fn resting_ball_hit_from_two_axes_stays() {
+        let bodies = [
+            ball(3, 3, Some(Dir::Right)),
+            ball(4, 3, None),
+            ball(4, 2, Some(Dir::Up)),
+        ];
+        assert_eq!(resolve(&bodies), [None, None, None]);
+    }
+
+    #[test]
+    fn stopped_front_stops_followers() {
+        // Front of the train converges with another mover, the rest of the train can't follow
+        let bodies = [
+            ball(1, 1, Some(Dir::Right)),
+            ball(2, 1, Some(Dir::Right)),
+            ball(4, 1, Some(Dir::Left)),
+        ];
+        assert_eq!(resolve(&bodies), [None, None, None]);
+    }
+
+    #[test]
+    fn resting_crate_acts_like_wall() {
+        let bodies = [ball(1, 1, Some(Dir::Right)), crate_body(2, 1, None)];
+        assert_eq!(resolve(&bodies), [None, None]);
+    }
+
+    #[test]
+    fn pushed_crate_moves_with_pusher() {
+        let bodies = [
+            ball(1, 1, Some(Dir::Right)),
+            crate_body(2, 1, Some(Dir::Right)),
+        ];
+        assert_eq!(resolve(&bodies), [Some(Dir::Right), Some(Dir::Right)]);
+    }
+
+    #[test]
+    fn crate_never_transfers_momentum() {
+        let bodies = [crate_body(1, 1, Some(Dir::Right)), ball(2, 1, None)];
+        assert_eq!(resolve(&bodies), [None, None]);
+    }
+}
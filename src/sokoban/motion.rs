@@ -0,0 +1,317 @@
+use std::time::Duration;
+
+use bevy::{ecs::system::Command, prelude::*};
+use bevy_tweening::{
+    component_animator_system,
+    lens::{TransformPositionLens, TransformScaleLens},
+    AnimationSystem, Animator, EaseFunction, Lens, Sequence, Tween, TweenCompleted, TweeningPlugin,
+};
+
+use super::{
+    animation::SpriteLook, ball::Ball, grid::GridConfig, momentum::Momentum,
+    settings::DisplaySettings, tile_behaviour::Sand, Dir, Pos,
+};
+
+pub struct MotionPlugin;
+
+impl Plugin for MotionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(TweeningPlugin)
+            .add_systems(
+                Update,
+                component_animator_system::<TextureAtlasSprite>
+                    .in_set(AnimationSystem::AnimationUpdate),
+            )
+            .add_systems(
+                PostUpdate,
+                (tween_pos_to_transform, squash_on_impact, grow_resurfaced).chain(),
+            )
+            .add_systems(Update, despawn_fallen.run_if(on_event::<TweenCompleted>()));
+    }
+}
+
+/// Undo, reset and other moves over several tiles take this long regardless of distance
+const JUMP_SECONDS: f32 = 0.15;
+const SQUASH_SECONDS: f32 = 0.06;
+const UNSQUASH_SECONDS: f32 = 0.15;
+const FALL_SECONDS: f32 = 0.35;
+/// `user_data` of the tween that finishes a fall into the void
+const FALL_COMPLETED: u64 = 1;
+
+/// Tracks how something moved last, to pick the easing of its next step
+#[derive(Component, Clone, Default)]
+pub struct Glide {
+    /// Seconds since startup of the last step
+    last_step: f32,
+    momentum: Option<Dir>,
+}
+
+/// Put back by undoing a fall into the void, grows back into place
+#[derive(Component, Clone, Copy, Default)]
+pub struct Resurfaced;
+
+fn tween_pos_to_transform(
+    mut cmds: Commands,
+    mut query: Query<
+        (
+            Entity,
+            Ref<Pos>,
+            &mut Transform,
+            Option<&mut Glide>,
+            Option<&Momentum>,
+        ),
+        Changed<Pos>,
+    >,
+    display: Res<DisplaySettings>,
+    grid: Res<GridConfig>,
+    fixed_time: Res<Time<Fixed>>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed_seconds();
+    let step = fixed_time.timestep();
+    for (entity, pos, mut transform, glide, momentum) in query.iter_mut() {
+        let target = grid.sprite_position(&pos).extend(transform.translation.z);
+        // The animator replaced below might have been growing a resurfaced entity
+        transform.scale = Vec3::ONE;
+
+        let Some(mut glide) = glide.filter(|_| !display.reduced_motion && !pos.is_added()) else {
+            cmds.entity(entity).remove::<Animator<Transform>>();
+            transform.translation = target;
+            continue;
+        };
+
+        let was_moving = now - glide.last_step <= step.as_secs_f32() * 1.5;
+        let keeps_moving = momentum.is_some_and(|momentum| momentum.is_some());
+        glide.last_step = now;
+
+        let tiles = transform.translation.truncate().distance(target.truncate()) / grid.tile_size;
+        let (ease, duration) = if tiles > 1.5 {
+            (
+                EaseFunction::QuadraticInOut,
+                Duration::from_secs_f32(JUMP_SECONDS),
+            )
+        } else {
+            let ease = match (was_moving, keeps_moving) {
+                (true, true) => EaseFunction::Linear,
+                (false, true) => EaseFunction::QuadraticIn,
+                (true, false) => EaseFunction::QuadraticOut,
+                (false, false) => EaseFunction::QuadraticInOut,
+            };
+            (ease, step)
+        };
+
+        // Starting from wherever the last tween left off also plays undo in reverse
+        cmds.entity(entity).insert(Animator::new(Tween::new(
+            ease,
+            duration,
+            TransformPositionLens {
+                start: transform.translation,
+                end: target,
+            },
+        )));
+    }
+}
+
+/// Scales the sprite of a ball, leaving the transform to the movement tween
+struct SquashLens {
+    /// Size of the unsquashed sprite
+    size: Vec2,
+    start: Vec2,
+    end: Vec2,
+}
+
+impl Lens<Sprite> for SquashLens {
+    fn lerp(&mut self, target: &mut Sprite, ratio: f32) {
+        target.custom_size = Some(self.size * self.start.lerp(self.end, ratio));
+    }
+}
+
+impl Lens<TextureAtlasSprite> for SquashLens {
+    fn lerp(&mut self, target: &mut TextureAtlasSprite, ratio: f32) {
+        target.custom_size = Some(self.size * self.start.lerp(self.end, ratio));
+    }
+}
+
+/// Squashes to `squashed` and springs back
+fn squash<T: Component>(size: Vec2, squashed: Vec2) -> Sequence<T>
+where
+    SquashLens: Lens<T>,
+{
+    Tween::new(
+        EaseFunction::QuadraticOut,
+        Duration::from_secs_f32(SQUASH_SECONDS),
+        SquashLens {
+            size,
+            start: Vec2::ONE,
+            end: squashed,
+        },
+    )
+    .then(Tween::new(
+        EaseFunction::BackOut,
+        Duration::from_secs_f32(UNSQUASH_SECONDS),
+        SquashLens {
+            size,
+            start: squashed,
+            end: Vec2::ONE,
+        },
+    ))
+}
+
+/// Flattens a ball against whatever stopped or bounced it
+fn squash_on_impact(
+    mut cmds: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &Pos,
+            &Momentum,
+            &mut Glide,
+            Option<(&Handle<TextureAtlas>, &TextureAtlasSprite)>,
+        ),
+        (With<Ball>, Changed<Momentum>),
+    >,
+    sand_query: Query<&Pos, With<Sand>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    display: Res<DisplaySettings>,
+    grid: Res<GridConfig>,
+) {
+    for (entity, pos, momentum, mut glide, sheet) in query.iter_mut() {
+        let previous = std::mem::replace(&mut glide.momentum, **momentum);
+        let Some(dir) = previous else {
+            continue;
+        };
+        let hit = match **momentum {
+            // Sand slows balls down instead of them hitting something
+            None => !sand_query.iter().any(|sand_pos| sand_pos == pos),
+            Some(new) => new == dir.opposite(),
+        };
+        if !hit || display.reduced_motion {
+            continue;
+        }
+
+        let squashed = match dir {
+            Dir::Left | Dir::Right => Vec2::new(0.7, 1.25),
+            Dir::Up | Dir::Down => Vec2::new(1.25, 0.7),
+        };
+        let tile = Vec2::splat(grid.tile_size);
+        match sheet {
+            Some((texture_atlas, sprite)) => {
+                // Frames of a sprite sheet don't have to be the size of a tile
+                let size = atlases
+                    .get(texture_atlas)
+                    .and_then(|atlas| atlas.textures.get(sprite.index))
+                    .map_or(tile, |frame| frame.size());
+                cmds.entity(entity)
+                    .insert(Animator::new(squash::<TextureAtlasSprite>(size, squashed)));
+            }
+            None => {
+                cmds.entity(entity)
+                    .insert(Animator::new(squash::<Sprite>(tile, squashed)));
+            }
+        }
+    }
+}
+
+/// Leaves a copy of the sprite behind that falls into the void,
+/// has to be added before the entity itself is despawned
+pub struct SpawnFall(pub Entity);
+
+impl Command for SpawnFall {
+    fn apply(self, world: &mut World) {
+        if world.resource::<DisplaySettings>().reduced_motion {
+            return;
+        }
+        let Some((pos, transform, parent)) = world
+            .query::<(&Pos, &Transform, &Parent)>()
+            .get(world, self.0)
+            .ok()
+            .map(|(pos, transform, parent)| (*pos, *transform, parent.get()))
+        else {
+            return;
+        };
+        let Some(look) = SpriteLook::of(world, self.0) else {
+            return;
+        };
+        let step = world.resource::<Time<Fixed>>().timestep();
+        let grid = *world.resource::<GridConfig>();
+        let target = grid.sprite_position(&pos).extend(transform.translation.z);
+
+        let fall = Tween::new(
+            EaseFunction::Linear,
+            step,
+            TransformPositionLens {
+                start: transform.translation,
+                end: target,
+            },
+        )
+        .then(
+            Tween::new(
+                EaseFunction::QuadraticIn,
+                Duration::from_secs_f32(FALL_SECONDS),
+                TransformScaleLens {
+                    start: transform.scale,
+                    end: Vec3::ZERO,
+                },
+            )
+            .with_completed_event(FALL_COMPLETED),
+        );
+        world.entity_mut(parent).with_children(|parent| {
+            let mut falling = parent.spawn((Animator::new(fall), Name::new("Falling")));
+            match look {
+                SpriteLook::Image(texture) => falling.insert(SpriteBundle {
+                    sprite: Sprite {
+                        anchor: grid.anchor(),
+                        ..default()
+                    },
+                    texture,
+                    transform,
+                    ..default()
+                }),
+                SpriteLook::Sheet {
+                    texture_atlas,
+                    index,
+                } => falling.insert(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite {
+                        index,
+                        anchor: grid.anchor(),
+                        ..default()
+                    },
+                    texture_atlas,
+                    transform,
+                    ..default()
+                }),
+            };
+        });
+    }
+}
+
+fn despawn_fallen(mut cmds: Commands, mut completed: EventReader<TweenCompleted>) {
+    for ev in completed.read() {
+        if ev.user_data == FALL_COMPLETED {
+            cmds.entity(ev.entity).despawn_recursive();
+        }
+    }
+}
+
+/// Plays the fall backwards when it is undone
+fn grow_resurfaced(
+    mut cmds: Commands,
+    mut query: Query<(Entity, &mut Transform), Added<Resurfaced>>,
+    display: Res<DisplaySettings>,
+) {
+    for (entity, mut transform) in query.iter_mut() {
+        cmds.entity(entity).remove::<Resurfaced>();
+        if display.reduced_motion {
+            continue;
+        }
+        transform.scale = Vec3::ZERO;
+        cmds.entity(entity).insert(Animator::new(Tween::new(
+            EaseFunction::QuadraticOut,
+            Duration::from_secs_f32(FALL_SECONDS),
+            TransformScaleLens {
+                start: Vec3::ZERO,
+                end: Vec3::ONE,
+            },
+        )));
+    }
+}
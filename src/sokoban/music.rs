@@ -0,0 +1,221 @@
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt},
+    audio::*,
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+    utils::HashMap,
+};
+use bevy_asset_loader::prelude::AssetCollection;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{
+    audio::VolumeSettings, level::LevelCollection, settings::SettingsReturn, GameState,
+    LEVEL_STATES,
+};
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_asset_loader(MusicManifestLoader)
+            .init_asset::<MusicManifest>()
+            .register_type::<MusicCollection>()
+            .add_systems(
+                Update,
+                (
+                    switch_track.run_if(
+                        state_changed::<GameState>().and_then(resource_exists::<MusicCollection>()),
+                    ),
+                    fade_music,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Seconds it takes for one track to fade into the next
+const CROSSFADE_SECONDS: f32 = 1.5;
+/// Fraction of the music volume left while the pause menu is open
+const PAUSE_DUCK: f32 = 0.35;
+/// Seconds it takes to duck under the pause menu and back
+const DUCK_SECONDS: f32 = 0.3;
+
+#[derive(Resource, Reflect, Default, Debug, AssetCollection)]
+#[reflect(Resource)]
+pub struct MusicCollection {
+    #[asset(path = "soundtrack.music")]
+    pub manifest: Handle<MusicManifest>,
+}
+
+/// Which track plays in which game state or level pack.
+/// States without a track keep playing whatever was playing before.
+#[derive(TypePath, TypeUuid, Debug, Asset)]
+#[uuid = "5d3f4b8e-1c2a-4f6d-9b7e-8a0c2e4d6f13"]
+pub struct MusicManifest {
+    states: HashMap<GameState, Handle<AudioSource>>,
+    /// Keyed by the asset path of the level pack
+    packs: HashMap<String, Handle<AudioSource>>,
+}
+
+impl MusicManifest {
+    fn track(&self, state: GameState, pack: &str) -> Option<&Handle<AudioSource>> {
+        let pack_track = LEVEL_STATES
+            .contains(&state)
+            .then(|| self.packs.get(pack))
+            .flatten();
+        pack_track.or_else(|| self.states.get(&state))
+    }
+}
+
+#[derive(Deserialize)]
+struct StringMusicManifest {
+    #[serde(default)]
+    states: HashMap<GameState, String>,
+    #[serde(default)]
+    packs: HashMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct MusicManifestLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum MusicManifestLoaderError {
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the ron: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for MusicManifestLoader {
+    type Asset = MusicManifest;
+    type Settings = ();
+    type Error = MusicManifestLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, std::result::Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let manifest = ron::de::from_bytes::<StringMusicManifest>(&bytes)?;
+
+            let states = manifest
+                .states
+                .into_iter()
+                .map(|(state, path)| (state, load_context.load(path)))
+                .collect();
+            let packs = manifest
+                .packs
+                .into_iter()
+                .map(|(pack, path)| (pack, load_context.load(path)))
+                .collect();
+
+            Ok(MusicManifest { states, packs })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["music"]
+    }
+}
+
+/// A looping track, faded in when spawned and despawned once faded out
+#[derive(Component)]
+struct Music {
+    track: Handle<AudioSource>,
+    /// Crossfade progress, multiplied with the music volume
+    level: f32,
+    fading_out: bool,
+}
+
+fn switch_track(
+    mut cmds: Commands,
+    mut music_query: Query<&mut Music>,
+    state: Res<State<GameState>>,
+    music: Res<MusicCollection>,
+    manifests: Res<Assets<MusicManifest>>,
+    levels: Res<LevelCollection>,
+    settings_return: Res<SettingsReturn>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(manifest) = manifests.get(&music.manifest) else {
+        return;
+    };
+    // The settings belong to the menu they were opened from, only a level keeps
+    // playing the track of its pack
+    let state = match *state.get() {
+        GameState::Settings => **settings_return,
+        state => state,
+    };
+    let pack = levels.pack(&asset_server);
+    let Some(track) = manifest.track(state, &pack) else {
+        return;
+    };
+
+    // Bring back the track if it is still fading out instead of starting it over
+    let mut playing = false;
+    for mut music in music_query.iter_mut() {
+        music.fading_out = music.track != *track || playing;
+        playing |= !music.fading_out;
+    }
+    if playing {
+        return;
+    }
+
+    cmds.spawn((
+        AudioBundle {
+            source: track.clone(),
+            settings: PlaybackSettings {
+                mode: PlaybackMode::Loop,
+                volume: Volume::Absolute(VolumeLevel::new(0.)),
+                ..default()
+            },
+        },
+        Music {
+            track: track.clone(),
+            level: 0.,
+            fading_out: false,
+        },
+        Name::new("Music"),
+    ));
+}
+
+fn fade_music(
+    mut cmds: Commands,
+    mut music_query: Query<(Entity, &mut Music, Option<&AudioSink>)>,
+    mut ducked: Local<f32>,
+    state: Res<State<GameState>>,
+    volume_settings: Res<VolumeSettings>,
+    time: Res<Time<Real>>,
+) {
+    let delta = time.delta_seconds();
+    let duck_step = delta / DUCK_SECONDS;
+    *ducked = if *state.get() == GameState::Pause {
+        (*ducked + duck_step).min(1.)
+    } else {
+        (*ducked - duck_step).max(0.)
+    };
+    let duck = 1. - *ducked * (1. - PAUSE_DUCK);
+
+    let fade_step = delta / CROSSFADE_SECONDS;
+    for (entity, mut music, sink) in music_query.iter_mut() {
+        if music.fading_out {
+            music.level -= fade_step;
+            if music.level <= 0. {
+                cmds.entity(entity).despawn_recursive();
+                continue;
+            }
+        } else {
+            music.level = (music.level + fade_step).min(1.);
+        }
+        // The sink only exists once the track started playing
+        if let Some(sink) = sink {
+            sink.set_volume(music.level * volume_settings.music_vol * duck);
+        }
+    }
+}
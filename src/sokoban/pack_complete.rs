@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::ActionState;
+
+use super::{
+    cleanup::DependOnState,
+    level::{LevelCollection, Levels},
+    level_complete::{format_time, PersonalBests},
+    theme::ActiveTheme,
+    ui::NineSliceButtonText,
+    GameState, SokobanActions,
+};
+
+const CURRENT_STATE: GameState = GameState::PackComplete;
+
+pub struct PackCompletePlugin;
+
+impl Plugin for PackCompletePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedButton>()
+            .add_event::<InteractionEvent>()
+            .add_systems(OnEnter(CURRENT_STATE), spawn_pack_summary)
+            .add_systems(
+                Update,
+                (
+                    handle_buttons,
+                    ui_navigation,
+                    handle_interaction,
+                    render_selected_border,
+                )
+                    .chain()
+                    .run_if(in_state(CURRENT_STATE)),
+            );
+    }
+}
+
+/// Personal bests of a pack added up
+#[derive(Default)]
+struct PackTotals {
+    levels: usize,
+    completed: usize,
+    moves: usize,
+    pushes: usize,
+    seconds: f32,
+    with_par: usize,
+    on_par: usize,
+}
+
+impl PackTotals {
+    fn new(levels: &Levels, bests: &PersonalBests, pack: &str) -> Self {
+        let mut totals = PackTotals {
+            levels: levels.len(),
+            ..default()
+        };
+        for (index, level) in levels.iter().enumerate() {
+            let best = bests.get(pack, index);
+            if let Some(par) = level.par {
+                totals.with_par += 1;
+                if best.is_some_and(|best| best.moves <= par as usize) {
+                    totals.on_par += 1;
+                }
+            }
+            let Some(best) = best else {
+                continue;
+            };
+            totals.completed += 1;
+            totals.moves += best.moves;
+            totals.pushes += best.pushes;
+            totals.seconds += best.seconds;
+        }
+        totals
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Levels completed: {}/{}", self.completed, self.levels),
+            format!("Total moves: {}", self.moves),
+            format!("Total pushes: {}", self.pushes),
+            format!("Total time: {}", format_time(self.seconds)),
+        ];
+        if self.with_par > 0 {
+            lines.push(format!(
+                "At or under par: {}/{}",
+                self.on_par, self.with_par
+            ));
+        }
+        lines
+    }
+}
+
+const ALL_BUTTONS: [PackCompleteButton; 2] = [
+    PackCompleteButton::LevelSelect,
+    PackCompleteButton::MainMenu,
+];
+
+#[derive(Component, Copy, Clone, PartialEq)]
+enum PackCompleteButton {
+    LevelSelect,
+    MainMenu,
+}
+
+impl From<PackCompleteButton> for GameState {
+    fn from(value: PackCompleteButton) -> Self {
+        match value {
+            PackCompleteButton::LevelSelect => GameState::LevelSelect,
+            PackCompleteButton::MainMenu => GameState::MainMenu,
+        }
+    }
+}
+
+impl From<PackCompleteButton> for String {
+    fn from(value: PackCompleteButton) -> Self {
+        match value {
+            PackCompleteButton::LevelSelect => "Level Select",
+            PackCompleteButton::MainMenu => "Main Menu",
+        }
+        .to_string()
+    }
+}
+
+fn spawn_pack_summary(
+    mut cmds: Commands,
+    theme: Res<ActiveTheme>,
+    bests: Res<PersonalBests>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    asset_server: Res<AssetServer>,
+    mut selected_button: ResMut<SelectedButton>,
+) {
+    **selected_button = 0;
+    let totals = levels
+        .get(&level_collection.levels)
+        .map(|levels| PackTotals::new(levels, &bests, &level_collection.pack(&asset_server)))
+        .unwrap_or_default();
+
+    let button_texture = theme.button.clone_weak();
+    let button_style = Style {
+        width: Val::Px(150.0),
+        height: Val::Px(50.0),
+        margin: UiRect {
+            top: Val::Px(6.),
+            bottom: Val::Px(6.),
+            ..default()
+        },
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        border: UiRect::all(Val::Px(2.)),
+        ..default()
+    };
+    let text_style = TextStyle {
+        font_size: 24.,
+        color: Color::BLACK,
+        ..default()
+    };
+
+    let parent = cmds
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::ANTIQUE_WHITE.into(),
+                ..default()
+            },
+            DependOnState::single(CURRENT_STATE),
+            Name::new("Pack Complete"),
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Pack Complete",
+                    TextStyle {
+                        font_size: 40.,
+                        ..text_style.clone()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(10.)),
+                    ..default()
+                }),
+            );
+            for line in totals.lines() {
+                parent.spawn(TextBundle::from_section(line, text_style.clone()));
+            }
+        })
+        .id();
+    for button in ALL_BUTTONS.iter() {
+        cmds.add(NineSliceButtonText {
+            button: *button,
+            style: button_style.clone(),
+            texture: button_texture.clone_weak(),
+            parent,
+        });
+    }
+}
+
+#[derive(Event, Deref, DerefMut)]
+struct InteractionEvent(pub PackCompleteButton);
+
+fn handle_interaction(
+    mut events: EventReader<InteractionEvent>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    for ev in events.read() {
+        game_state.set(GameState::from(**ev));
+    }
+}
+
+fn handle_buttons(
+    buttons: Query<(&PackCompleteButton, &Interaction), Changed<Interaction>>,
+    mut event_writer: EventWriter<InteractionEvent>,
+) {
+    buttons.iter().for_each(|button| {
+        if let (button, Interaction::Pressed) = button {
+            event_writer.send(InteractionEvent(*button));
+        }
+    });
+}
+
+#[derive(Resource, Deref, DerefMut, Default)]
+struct SelectedButton(pub usize);
+
+fn ui_navigation(
+    navigation_actions: Query<&ActionState<SokobanActions>>,
+    mut selected_button: ResMut<SelectedButton>,
+    mut event_writer: EventWriter<InteractionEvent>,
+) {
+    let Ok(navigation_actions) = navigation_actions.get_single() else {
+        return;
+    };
+    if navigation_actions.just_pressed(SokobanActions::UiNavUp) {
+        selected_button.0 = (**selected_button + ALL_BUTTONS.len() - 1) % ALL_BUTTONS.len();
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavDown) {
+        selected_button.0 = (**selected_button + 1) % ALL_BUTTONS.len();
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavSelect) {
+        event_writer.send(InteractionEvent(ALL_BUTTONS[**selected_button]));
+    }
+    if navigation_actions.just_pressed(SokobanActions::Escape) {
+        event_writer.send(InteractionEvent(PackCompleteButton::LevelSelect));
+    }
+}
+
+fn render_selected_border(
+    selected_button: Res<SelectedButton>,
+    mut buttons: Query<(&PackCompleteButton, &mut BorderColor)>,
+) {
+    for (button, mut border_color) in buttons.iter_mut() {
+        if *button == ALL_BUTTONS[**selected_button] {
+            *border_color = BorderColor(Color::RED);
+        } else {
+            *border_color = BorderColor(Color::NONE);
+        }
+    }
+}
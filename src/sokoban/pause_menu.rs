@@ -1,13 +1,18 @@
-use std::ops::AddAssign;
-
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::ActionState;
 
 use super::{
-    cleanup::DependOnState, level_select::CurrentLevel, ui::NineSliceButtonText, AssetsCollection,
+    cleanup::DependOnState,
+    level::{LevelCollection, Levels},
+    level_select::CurrentLevel,
+    settings::SettingsReturn,
+    theme::ActiveTheme,
+    ui::NineSliceButtonText,
     GameState, SokobanActions,
 };
 
+const DISABLED_TEXT_COLOR: Color = Color::GRAY;
+
 pub struct PauseMenuPlugin;
 
 impl Plugin for PauseMenuPlugin {
@@ -23,16 +28,18 @@ impl Plugin for PauseMenuPlugin {
                     ui_navigation.before(handle_interaction),
                     handle_interaction,
                     render_selected_border,
+                    render_disabled,
                 )
                     .run_if(in_state(GameState::Pause)),
             );
     }
 }
 
-const ALL_BUTTONS: [PauseMenuButton; 5] = [
+const ALL_BUTTONS: [PauseMenuButton; 6] = [
     PauseMenuButton::Resume,
     PauseMenuButton::NextLevel,
     PauseMenuButton::PrevLevel,
+    PauseMenuButton::Settings,
     PauseMenuButton::ReturnToLevelSelect,
     PauseMenuButton::ReturnToMain,
 ];
@@ -42,6 +49,7 @@ enum PauseMenuButton {
     Resume,
     NextLevel,
     PrevLevel,
+    Settings,
     ReturnToLevelSelect,
     ReturnToMain,
 }
@@ -52,18 +60,31 @@ impl From<PauseMenuButton> for GameState {
             PauseMenuButton::Resume => GameState::Play,
             PauseMenuButton::NextLevel => GameState::LevelTransition,
             PauseMenuButton::PrevLevel => GameState::LevelTransition,
+            PauseMenuButton::Settings => GameState::Settings,
             PauseMenuButton::ReturnToLevelSelect => GameState::LevelSelect,
             PauseMenuButton::ReturnToMain => GameState::MainMenu,
         }
     }
 }
 
+impl PauseMenuButton {
+    /// Next and previous level are disabled at the ends of the pack
+    fn is_enabled(&self, current_level: &CurrentLevel, level_count: usize) -> bool {
+        match self {
+            PauseMenuButton::NextLevel => current_level.has_next(level_count),
+            PauseMenuButton::PrevLevel => current_level.has_prev(),
+            _ => true,
+        }
+    }
+}
+
 impl From<PauseMenuButton> for String {
     fn from(value: PauseMenuButton) -> Self {
         match value {
             PauseMenuButton::Resume => "Resume",
             PauseMenuButton::NextLevel => "Next Level",
             PauseMenuButton::PrevLevel => "Previous Level",
+            PauseMenuButton::Settings => "Settings",
             PauseMenuButton::ReturnToLevelSelect => "Level Select",
             PauseMenuButton::ReturnToMain => "Main Menu",
         }
@@ -71,8 +92,19 @@ impl From<PauseMenuButton> for String {
     }
 }
 
-fn setup(mut cmds: Commands, assets: Res<AssetsCollection>) {
-    let button_texture = assets.button.clone_weak();
+fn setup(
+    mut cmds: Commands,
+    theme: Res<ActiveTheme>,
+    mut selected_button: ResMut<SelectedButton>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+) {
+    let level_count = level_collection.level_count(&levels);
+    if !ALL_BUTTONS[**selected_button].is_enabled(&current_level, level_count) {
+        **selected_button = 0;
+    }
+    let button_texture = theme.button.clone_weak();
     let button_style = Style {
         width: Val::Px(150.0),
         height: Val::Px(65.0),
@@ -122,14 +154,24 @@ fn handle_interaction(
     mut events: EventReader<InteractionEvent>,
     mut game_state: ResMut<NextState<GameState>>,
     mut current_level: ResMut<CurrentLevel>,
+    mut settings_return: ResMut<SettingsReturn>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
 ) {
+    let level_count = level_collection.level_count(&levels);
     for ev in events.read() {
+        if !ev.is_enabled(&current_level, level_count) {
+            continue;
+        }
         match **ev {
             PauseMenuButton::NextLevel => {
-                current_level.add_assign(1);
+                current_level.0 += 1;
             }
             PauseMenuButton::PrevLevel => {
-                current_level.0 = current_level.saturating_sub(1);
+                current_level.0 -= 1;
+            }
+            PauseMenuButton::Settings => {
+                **settings_return = GameState::Pause;
             }
             _ => {}
         }
@@ -156,15 +198,29 @@ fn ui_navigation(
     navigation_actions: Query<&ActionState<SokobanActions>>,
     mut selected_button: ResMut<SelectedButton>,
     mut event_writer: EventWriter<InteractionEvent>,
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
 ) {
     let Ok(navigation_actions) = navigation_actions.get_single() else {
         return;
     };
+    let level_count = level_collection.level_count(&levels);
+    // Disabled buttons are skipped, Resume is always enabled so this terminates
+    let step = |selected: usize, offset: usize| {
+        let mut selected = selected;
+        loop {
+            selected = (selected + offset) % ALL_BUTTONS.len();
+            if ALL_BUTTONS[selected].is_enabled(&current_level, level_count) {
+                return selected;
+            }
+        }
+    };
     if navigation_actions.just_pressed(SokobanActions::UiNavUp) {
-        selected_button.0 = (**selected_button + ALL_BUTTONS.len() - 1) % ALL_BUTTONS.len();
+        selected_button.0 = step(**selected_button, ALL_BUTTONS.len() - 1);
     }
     if navigation_actions.just_pressed(SokobanActions::UiNavDown) {
-        selected_button.0 = (**selected_button + 1) % ALL_BUTTONS.len();
+        selected_button.0 = step(**selected_button, 1);
     }
     if navigation_actions.just_pressed(SokobanActions::UiNavSelect) {
         event_writer.send(InteractionEvent(ALL_BUTTONS[**selected_button]));
@@ -183,3 +239,26 @@ fn render_selected_border(
         }
     }
 }
+
+fn render_disabled(
+    current_level: Res<CurrentLevel>,
+    level_collection: Res<LevelCollection>,
+    levels: Res<Assets<Levels>>,
+    buttons: Query<(&PauseMenuButton, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    let level_count = level_collection.level_count(&levels);
+    for (button, children) in buttons.iter() {
+        let color = if button.is_enabled(&current_level, level_count) {
+            Color::WHITE
+        } else {
+            DISABLED_TEXT_COLOR
+        };
+        let mut iter = texts.iter_many_mut(children);
+        while let Some(mut text) = iter.fetch_next() {
+            for section in text.sections.iter_mut() {
+                section.style.color = color;
+            }
+        }
+    }
+}
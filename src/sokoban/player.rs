@@ -1,11 +1,27 @@
-use bevy::{ecs::system::Command, log, prelude::*};
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::{schedule::SystemConfigs, system::Command},
+    log,
+    prelude::*,
+};
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::{
+    animation::SpriteAnimation,
+    auto_walk::AutoWalk,
     collision::{CollisionMap, CollisionResult},
+    controls::Controls,
+    crates::Crate,
     history::{HandleHistoryEvents, History, HistoryEvent},
+    inventory::{Inventory, Lock, UseKey},
     momentum::{any_momentum_left, Momentum},
-    AssetsCollection, Dir, DynamicBundle, GameState, Pos, SokobanEvent,
+    replay::{Recording, ReplayPlayback, ReplayStep},
+    simulation::SimulationSettings,
+    theme::ActiveTheme,
+    tile_behaviour::{Switch, ToggleSwitch},
+    Dir, DynamicBundle, GameState, Pos, SokobanBlock, SokobanEvent,
 };
 
 pub struct PlayerPlugin;
@@ -13,21 +29,32 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(InputManagerPlugin::<PlayerActions>::default())
+            .register_type::<InputBuffer>()
+            .init_resource::<InputBuffer>()
             .add_systems(Startup, setup)
-            .add_systems(
-                Update,
-                player_movement
-                    .before(HandleHistoryEvents)
-                    .run_if(not(any_momentum_left()))
-                    .run_if(in_state(GameState::Play)),
-            );
+            .add_systems(OnExit(GameState::Play), clear_input_buffer)
+            .add_systems(Update, movement_systems());
     }
 }
 
+fn movement_systems() -> SystemConfigs {
+    (
+        // A press that starts a push would otherwise be buffered as well once
+        // the push gave things momentum, moving the player twice
+        buffer_input
+            .before(player_movement)
+            .run_if(any_momentum_left()),
+        player_movement
+            .before(HandleHistoryEvents)
+            .run_if(not(any_momentum_left())),
+    )
+        .run_if(in_state(GameState::Play))
+}
+
 #[derive(Component, Clone)]
 pub struct Player;
 
-#[derive(Actionlike, Clone, Copy, Hash, Debug, PartialEq, Eq, Reflect)]
+#[derive(Actionlike, Clone, Copy, Hash, Debug, PartialEq, Eq, Reflect, Serialize, Deserialize)]
 pub enum PlayerActions {
     Up,
     Right,
@@ -35,6 +62,34 @@ pub enum PlayerActions {
     Left,
 }
 
+/// Direction the player last moved in and when, for the animations
+#[derive(Component, Clone, Copy)]
+pub struct Facing {
+    pub dir: Dir,
+    /// Seconds since startup
+    pub moved_at: f32,
+}
+
+impl Default for Facing {
+    fn default() -> Self {
+        Self {
+            dir: Dir::Down,
+            moved_at: f32::NEG_INFINITY,
+        }
+    }
+}
+
+impl From<Dir> for PlayerActions {
+    fn from(value: Dir) -> PlayerActions {
+        match value {
+            Dir::Up => PlayerActions::Up,
+            Dir::Left => PlayerActions::Left,
+            Dir::Down => PlayerActions::Down,
+            Dir::Right => PlayerActions::Right,
+        }
+    }
+}
+
 impl From<PlayerActions> for Dir {
     fn from(value: PlayerActions) -> Dir {
         match value {
@@ -62,7 +117,9 @@ impl SpawnPlayer {
 
 impl Command for SpawnPlayer {
     fn apply(self, world: &mut World) {
-        let texture = world.resource::<AssetsCollection>().player.clone();
+        let theme = world.resource::<ActiveTheme>();
+        let texture_atlas = theme.player_sheet.clone();
+        let animations = theme.player_animations.clone();
 
         world
             .entity_mut(self.tilemap_entity)
@@ -72,40 +129,33 @@ impl Command for SpawnPlayer {
                     Player,
                     self.pos,
                     History::<Pos>::default(),
+                    Inventory::default(),
+                    History::<Inventory>::default(),
                     DynamicBundle::default(),
-                    SpriteBundle {
-                        texture,
+                    SpriteSheetBundle {
+                        texture_atlas,
                         transform: Transform::from_translation(2. * Vec3::Z),
                         ..default()
                     },
+                    SpriteAnimation::default(),
+                    animations,
+                    Facing::default(),
                     MovementTimer::default(),
                 ));
             });
     }
 }
 
-fn setup(mut cmds: Commands) {
+fn setup(mut cmds: Commands, controls: Res<Controls>) {
     cmds.spawn((
         (InputManagerBundle::<PlayerActions> {
-            input_map: player_actions(),
+            input_map: controls.player_input_map(),
             ..default()
         },),
         Name::new("PlayerActions"),
     ));
 }
 
-fn player_actions() -> InputMap<PlayerActions> {
-    use PlayerActions::*;
-    let mut input_map = InputMap::default();
-
-    input_map.insert(KeyCode::W, Up);
-    input_map.insert(KeyCode::D, Right);
-    input_map.insert(KeyCode::S, Down);
-    input_map.insert(KeyCode::A, Left);
-
-    input_map
-}
-
 #[derive(Clone, Debug, Component, Deref, DerefMut)]
 pub struct MovementTimer(pub Timer);
 
@@ -115,16 +165,91 @@ impl Default for MovementTimer {
     }
 }
 
+/// Most directions the player can set the input buffer to hold
+pub const MAX_INPUT_BUFFER: usize = 8;
+
+/// Directions pressed while momentum was still resolving, applied in order once
+/// everything has come to rest
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct InputBuffer {
+    queue: VecDeque<Dir>,
+    /// Maximum amount of buffered directions, 0 disables buffering. Set in the settings.
+    pub capacity: usize,
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            capacity: 3,
+        }
+    }
+}
+
+impl InputBuffer {
+    pub fn push(&mut self, dir: Dir) {
+        if self.queue.len() < self.capacity {
+            self.queue.push_back(dir);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Dir> {
+        self.queue.pop_front()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn change_capacity(&mut self, up: bool) {
+        self.capacity = if up {
+            (self.capacity + 1).min(MAX_INPUT_BUFFER)
+        } else {
+            self.capacity.saturating_sub(1)
+        };
+        self.queue.truncate(self.capacity);
+    }
+}
+
+fn buffer_input(
+    player_actions: Query<&ActionState<PlayerActions>>,
+    mut input_buffer: ResMut<InputBuffer>,
+) {
+    let Ok(player_actions) = player_actions.get_single() else {
+        return;
+    };
+    for action in player_actions.get_just_pressed() {
+        input_buffer.push(Dir::from(action));
+    }
+}
+
+pub fn clear_input_buffer(mut input_buffer: ResMut<InputBuffer>) {
+    input_buffer.clear();
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn player_movement(
-    mut player_q: Query<(&Pos, &mut MovementTimer), With<Player>>,
+    mut player_q: Query<(&Pos, &Inventory, &mut MovementTimer, &mut Facing), With<Player>>,
     mut sokoban_entities: Query<&mut Momentum>,
+    lock_query: Query<&Lock>,
+    crate_query: Query<(), With<Crate>>,
+    switch_query: Query<(), With<Switch>>,
     player_actions: Query<&ActionState<PlayerActions>>,
     mut history_events: EventWriter<HistoryEvent>,
     mut sokoban_events: EventWriter<SokobanEvent>,
+    mut use_key_events: EventWriter<UseKey>,
+    mut toggle_switch_events: EventWriter<ToggleSwitch>,
     collision: Res<CollisionMap>,
+    settings: Res<SimulationSettings>,
+    mut input_buffer: ResMut<InputBuffer>,
+    mut auto_walk: ResMut<AutoWalk>,
+    mut recording: ResMut<Recording>,
+    mut playback: ResMut<ReplayPlayback>,
     time: Res<Time>,
 ) {
-    let Ok((player_pos, mut movement_timer)) = player_q.get_single_mut() else {
+    let Ok((player_pos, inventory, mut movement_timer, mut facing)) = player_q.get_single_mut()
+    else {
         return;
     };
 
@@ -132,19 +257,45 @@ pub fn player_movement(
         .get_single()
         .expect("Player input map should exist");
 
-    movement_timer.tick(time.delta());
+    movement_timer.tick(time.delta().mul_f32(settings.speed));
 
     if !movement_timer.finished() {
         return;
     }
 
-    for direction in player_actions
-        .get_pressed()
-        .iter()
-        .map(|action| Dir::from(*action))
-    {
+    // Replays ignore the player, buffered directions take precedence over held keys
+    let mut held = false;
+    let directions = if playback.is_playing() {
+        match playback.next_move() {
+            Some(direction) => vec![direction],
+            None => return,
+        }
+    } else if let Some(direction) = input_buffer.pop() {
+        vec![direction]
+    } else if let Some(step) = auto_walk.pop() {
+        // Walks never push, stop if something got in the way
+        if !step.push
+            && collision
+                .get(IVec2::from(player_pos) + IVec2::from(step.dir))
+                .is_some()
+        {
+            auto_walk.clear();
+            return;
+        }
+        vec![step.dir]
+    } else {
+        held = true;
+        player_actions
+            .get_pressed()
+            .iter()
+            .map(|action| Dir::from(*action))
+            .collect()
+    };
+
+    for direction in directions {
         movement_timer.reset();
-        match collision.push_collision(IVec2::from(player_pos), direction) {
+        let is_crate = |entity| crate_query.contains(entity);
+        match collision.push_collision(IVec2::from(player_pos), direction, is_crate) {
             CollisionResult::Push(push) => {
                 for e in push.iter() {
                     sokoban_entities
@@ -153,13 +304,50 @@ pub fn player_movement(
                         .replace(direction);
                 }
                 history_events.send(HistoryEvent::Record);
+                recording.record(ReplayStep::Move {
+                    dir: direction,
+                    push: push.len() > 1,
+                });
+                *facing = Facing {
+                    dir: direction,
+                    moved_at: time.elapsed_seconds(),
+                };
                 if push.len() == 1 {
                     sokoban_events.send(SokobanEvent::PlayerMoved);
+                } else {
+                    sokoban_events.send(SokobanEvent::PlayerPush);
                 }
-                sokoban_events.send(SokobanEvent::PlayerPush);
                 break;
             }
             CollisionResult::Wall => {
+                let dest = IVec2::from(player_pos) + IVec2::from(direction);
+                if let Some((blocking, block)) = collision.get(dest) {
+                    // Pushing a ball against a wall, once and not on every
+                    // repeat while the direction is held
+                    let fresh =
+                        !held || player_actions.just_pressed(PlayerActions::from(direction));
+                    if block == SokobanBlock::Dynamic && fresh {
+                        sokoban_events.send(SokobanEvent::BallHitWall);
+                    }
+                    if inventory.keys > 0 && lock_query.get(blocking).is_ok_and(|lock| !lock.open) {
+                        history_events.send(HistoryEvent::Record);
+                        recording.record(ReplayStep::Move {
+                            dir: direction,
+                            push: false,
+                        });
+                        use_key_events.send(UseKey(blocking));
+                        break;
+                    }
+                    if switch_query.contains(blocking) {
+                        history_events.send(HistoryEvent::Record);
+                        recording.record(ReplayStep::Move {
+                            dir: direction,
+                            push: false,
+                        });
+                        toggle_switch_events.send(ToggleSwitch(blocking));
+                        break;
+                    }
+                }
                 log::debug!("Can't move");
             }
             CollisionResult::OutOfBounds => {
@@ -168,3 +356,120 @@ pub fn player_movement(
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::sokoban::{ball::Ball, collision::sync_collision_map};
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_state::<GameState>()
+            .insert_resource(State::new(GameState::Play))
+            .insert_resource(CollisionMap::new(UVec2::new(5, 1)))
+            .init_resource::<Time>()
+            .init_resource::<SimulationSettings>()
+            .init_resource::<InputBuffer>()
+            .init_resource::<AutoWalk>()
+            .init_resource::<Recording>()
+            .init_resource::<ReplayPlayback>()
+            .add_event::<HistoryEvent>()
+            .add_event::<SokobanEvent>()
+            .add_event::<UseKey>()
+            .add_event::<ToggleSwitch>()
+            .add_systems(
+                Update,
+                (
+                    sync_collision_map.before(player_movement),
+                    movement_systems(),
+                ),
+            );
+
+        let mut movement_timer = MovementTimer::default();
+        movement_timer.tick(Duration::from_secs(1));
+        app.world.spawn((
+            Player,
+            Pos::new(0, 0),
+            Inventory::default(),
+            movement_timer,
+            Facing::default(),
+            DynamicBundle::default(),
+        ));
+        app
+    }
+
+    fn spawn_ball(app: &mut App, x: u32) -> Entity {
+        app.world
+            .spawn((Ball, Pos::new(x, 0), DynamicBundle::default()))
+            .id()
+    }
+
+    fn press(app: &mut App, action: PlayerActions) {
+        let mut action_state = ActionState::<PlayerActions>::default();
+        action_state.press(action);
+        app.world.spawn(action_state);
+    }
+
+    #[test]
+    fn press_starting_a_push_is_not_buffered() {
+        let mut app = app();
+        spawn_ball(&mut app, 1);
+        press(&mut app, PlayerActions::Right);
+        app.update();
+
+        assert_eq!(app.world.resource::<Recording>().summary().pushes, 1);
+        assert_eq!(app.world.resource_mut::<InputBuffer>().pop(), None);
+    }
+
+    #[test]
+    fn press_while_momentum_resolves_is_buffered_once() {
+        let mut app = app();
+        spawn_ball(&mut app, 1);
+        let mut balls = app.world.query_filtered::<&mut Momentum, With<Ball>>();
+        balls.single_mut(&mut app.world).replace(Dir::Right);
+        press(&mut app, PlayerActions::Right);
+        app.update();
+
+        let mut input_buffer = app.world.resource_mut::<InputBuffer>();
+        assert_eq!(input_buffer.pop(), Some(Dir::Right));
+        assert_eq!(input_buffer.pop(), None);
+        assert_eq!(app.world.resource::<Recording>().summary().moves, 0);
+    }
+
+    #[test]
+    fn crate_blocks_on_the_ball_it_is_pushed_into() {
+        let mut app = app();
+        let crate_entity = app
+            .world
+            .spawn((Crate, Pos::new(1, 0), DynamicBundle::default()))
+            .id();
+        let ball = spawn_ball(&mut app, 2);
+        press(&mut app, PlayerActions::Right);
+        app.update();
+
+        assert_eq!(app.world.resource::<Recording>().summary().moves, 0);
+        for entity in [crate_entity, ball] {
+            assert!(app.world.get::<Momentum>(entity).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn crate_alone_is_pushed() {
+        let mut app = app();
+        let crate_entity = app
+            .world
+            .spawn((Crate, Pos::new(1, 0), DynamicBundle::default()))
+            .id();
+        spawn_ball(&mut app, 3);
+        press(&mut app, PlayerActions::Right);
+        app.update();
+
+        assert_eq!(app.world.resource::<Recording>().summary().pushes, 1);
+        assert_eq!(
+            **app.world.get::<Momentum>(crate_entity).unwrap(),
+            Some(Dir::Right)
+        );
+    }
+}
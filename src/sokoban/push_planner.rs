@@ -0,0 +1,397 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    log,
+    prelude::*,
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
+};
+
+use super::{
+    auto_walk::{cursor_tile, find_path, walkable, AutoWalk, HazardQuery},
+    ball::Ball,
+    cleanup::DependOnState,
+    collision::CollisionMap,
+    crates::Crate,
+    grid::GridConfig,
+    momentum::any_momentum_left,
+    player::{player_movement, Player},
+    tile_behaviour::{Ice, Rubber, Sand, Void},
+    Dir, GameState, Pos, LEVEL_STATES,
+};
+
+pub struct PushPlannerPlugin;
+
+impl Plugin for PushPlannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PushPlan>()
+            .init_resource::<DragSelection>()
+            .add_systems(OnExit(GameState::Play), clear_push_plan)
+            .add_systems(
+                OnTransition {
+                    from: GameState::LevelTransition,
+                    to: GameState::Play,
+                },
+                spawn_plan_status,
+            )
+            .add_systems(
+                Update,
+                (
+                    cancel_push_plan,
+                    (select_target, execute_push_plan)
+                        .chain()
+                        .before(player_movement)
+                        .run_if(not(any_momentum_left())),
+                    plan_status,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Play)),
+            );
+    }
+}
+
+/// Upper bound of cells a ball rolls before the push is considered unpredictable
+const MAX_ROLL: usize = 256;
+/// How long a planner message stays on screen
+const STATUS_SECONDS: f32 = 2.;
+
+#[derive(Clone, Copy, Debug)]
+struct PlannedPush {
+    /// Where the ball rests before the push
+    from: IVec2,
+    dir: Dir,
+}
+
+/// Pushes that move the selected ball to its target, executed one at a time
+#[derive(Resource, Default)]
+pub struct PushPlan {
+    ball: Option<Entity>,
+    target: IVec2,
+    steps: VecDeque<PlannedPush>,
+}
+
+impl PushPlan {
+    pub fn clear(&mut self) {
+        self.ball = None;
+        self.steps.clear();
+    }
+}
+
+/// Ball or crate picked up with the mouse, waiting for the button to be released
+#[derive(Resource, Default)]
+struct DragSelection(Option<Entity>);
+
+#[derive(Component)]
+struct PlanStatus(Timer);
+
+/// The level as seen by the planner, the chosen ball is the only thing that moves
+struct Board<'a> {
+    collision: &'a CollisionMap,
+    ball: Entity,
+    player: Entity,
+    rolls: bool,
+    rubber: HashSet<IVec2>,
+    sand: HashSet<IVec2>,
+    void: HashSet<IVec2>,
+    ice: HashSet<IVec2>,
+}
+
+impl Board<'_> {
+    /// Anything but the ball and the player, which are tracked separately
+    fn blocked(&self, pos: IVec2) -> bool {
+        !self.collision.in_bounds(pos)
+            || self
+                .collision
+                .get(pos)
+                .is_some_and(|(entity, _)| entity != self.ball && entity != self.player)
+    }
+
+    fn walkable(&self, pos: IVec2, ball: IVec2) -> bool {
+        pos != ball && !self.blocked(pos) && !self.void.contains(&pos) && !self.ice.contains(&pos)
+    }
+
+    /// Cells the player can reach without pushing, the smallest one names the region
+    fn reachable(&self, player: IVec2, ball: IVec2) -> (HashSet<IVec2>, IVec2) {
+        let mut reached = HashSet::default();
+        reached.insert(player);
+        let mut queue = VecDeque::from([player]);
+        let mut smallest = player;
+        while let Some(current) = queue.pop_front() {
+            for dir in Dir::ALL {
+                let next = current + IVec2::from(dir);
+                if self.walkable(next, ball) && reached.insert(next) {
+                    if (next.y, next.x) < (smallest.y, smallest.x) {
+                        smallest = next;
+                    }
+                    queue.push_back(next);
+                }
+            }
+        }
+        (reached, smallest)
+    }
+
+    /// Where the ball comes to rest after being pushed from `from`.
+    /// The player follows into `from`, so the ball must never roll back into it.
+    fn push(&self, from: IVec2, dir: Dir) -> Option<IVec2> {
+        let player = from;
+        let mut pos = from + IVec2::from(dir);
+        if self.blocked(pos) || self.void.contains(&pos) || self.ice.contains(&player) {
+            return None;
+        }
+        if !self.rolls || self.sand.contains(&pos) {
+            return Some(pos);
+        }
+
+        let mut dir = dir;
+        for _ in 0..MAX_ROLL {
+            if self.rubber.contains(&(pos + IVec2::from(dir))) {
+                dir = dir.opposite();
+            }
+            let next = pos + IVec2::from(dir);
+            if next == player {
+                return None;
+            }
+            // Walls, rubber and other balls stop it, balls get the momentum instead
+            if self.blocked(next) {
+                return Some(pos);
+            }
+            pos = next;
+            if self.void.contains(&pos) {
+                return None;
+            }
+            if self.sand.contains(&pos) {
+                return Some(pos);
+            }
+        }
+        None
+    }
+
+    /// Breadth first search over ball positions and the region the player is in
+    fn plan(&self, ball: IVec2, player: IVec2, target: IVec2) -> Option<Vec<PlannedPush>> {
+        let start = (ball, self.reachable(player, ball).1);
+        let mut came_from: HashMap<(IVec2, IVec2), Option<((IVec2, IVec2), PlannedPush)>> =
+            HashMap::default();
+        came_from.insert(start, None);
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(state) = queue.pop_front() {
+            let (ball, region) = state;
+            if ball == target {
+                let mut steps = Vec::new();
+                let mut current = state;
+                while let Some(Some((previous, step))) = came_from.get(&current) {
+                    steps.push(*step);
+                    current = *previous;
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+
+            let (reached, _) = self.reachable(region, ball);
+            for dir in Dir::ALL {
+                if !reached.contains(&(ball - IVec2::from(dir))) {
+                    continue;
+                }
+                let Some(rest) = self.push(ball, dir) else {
+                    continue;
+                };
+                let next = (rest, self.reachable(ball, rest).1);
+                if !came_from.contains_key(&next) {
+                    came_from.insert(next, Some((state, PlannedPush { from: ball, dir })));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn select_target(
+    mouse_buttons: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    player_query: Query<(Entity, &Pos), With<Player>>,
+    pushable_query: Query<(&Pos, Has<Crate>), Or<(With<Ball>, With<Crate>)>>,
+    tile_query: Query<(&Pos, Has<Rubber>, Has<Sand>, Has<Void>, Has<Ice>)>,
+    collision: Res<CollisionMap>,
+    mut selection: ResMut<DragSelection>,
+    mut plan: ResMut<PushPlan>,
+    mut status_query: Query<(&mut Text, &mut PlanStatus)>,
+    grid: Res<GridConfig>,
+) {
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        selection.0 = cursor_tile(&window_query, &camera_query, &grid)
+            .and_then(|tile| collision.get(tile))
+            .map(|(entity, _)| entity)
+            .filter(|entity| pushable_query.contains(*entity));
+    }
+    if !mouse_buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(ball) = selection.0.take() else {
+        return;
+    };
+    let Some(target) = cursor_tile(&window_query, &camera_query, &grid) else {
+        return;
+    };
+    let (Ok((player, player_pos)), Ok((ball_pos, is_crate))) =
+        (player_query.get_single(), pushable_query.get(ball))
+    else {
+        return;
+    };
+    let ball_pos = IVec2::from(ball_pos);
+    if ball_pos == target {
+        return;
+    }
+
+    let mut board = Board {
+        collision: &collision,
+        ball,
+        player,
+        rolls: !is_crate,
+        rubber: HashSet::default(),
+        sand: HashSet::default(),
+        void: HashSet::default(),
+        ice: HashSet::default(),
+    };
+    for (pos, rubber, sand, void, ice) in tile_query.iter() {
+        let pos = IVec2::from(pos);
+        for (set, is) in [
+            (&mut board.rubber, rubber),
+            (&mut board.sand, sand),
+            (&mut board.void, void),
+            (&mut board.ice, ice),
+        ] {
+            if is {
+                set.insert(pos);
+            }
+        }
+    }
+
+    match board.plan(ball_pos, IVec2::from(player_pos), target) {
+        Some(steps) => {
+            log::debug!("Planned {} pushes to {target}", steps.len());
+            plan.ball = Some(ball);
+            plan.target = target;
+            plan.steps = steps.into();
+        }
+        None => show_status(&mut status_query, "Can't get it there"),
+    }
+}
+
+fn execute_push_plan(
+    mut plan: ResMut<PushPlan>,
+    mut auto_walk: ResMut<AutoWalk>,
+    player_query: Query<&Pos, With<Player>>,
+    ball_query: Query<&Pos>,
+    hazard_query: HazardQuery,
+    mut status_query: Query<(&mut Text, &mut PlanStatus)>,
+    collision: Res<CollisionMap>,
+) {
+    let Some(ball) = plan.ball else {
+        return;
+    };
+    if auto_walk.is_walking() {
+        return;
+    }
+    let (Ok(player_pos), Ok(ball_pos)) = (player_query.get_single(), ball_query.get(ball)) else {
+        plan.clear();
+        return;
+    };
+    let ball_pos = IVec2::from(ball_pos);
+
+    let Some(step) = plan.steps.pop_front() else {
+        if ball_pos != plan.target {
+            show_status(&mut status_query, "Something got in the way");
+        }
+        plan.clear();
+        return;
+    };
+    if ball_pos != step.from {
+        show_status(&mut status_query, "Something got in the way");
+        plan.clear();
+        return;
+    }
+
+    // Walk on the live map, other things may have moved since planning
+    let push_from = step.from - IVec2::from(step.dir);
+    let path = find_path(
+        IVec2::from(player_pos),
+        push_from,
+        walkable(&collision, &hazard_query),
+    );
+    match path {
+        Some(path) => {
+            auto_walk.start(path);
+            auto_walk.then_push(step.dir);
+        }
+        None => {
+            show_status(&mut status_query, "Something got in the way");
+            plan.clear();
+        }
+    }
+}
+
+fn cancel_push_plan(
+    keys: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut plan: ResMut<PushPlan>,
+) {
+    if plan.ball.is_some()
+        && (keys.get_just_pressed().next().is_some()
+            || gamepad_buttons.get_just_pressed().next().is_some())
+    {
+        plan.clear();
+    }
+}
+
+fn clear_push_plan(mut plan: ResMut<PushPlan>, mut selection: ResMut<DragSelection>) {
+    plan.clear();
+    selection.0 = None;
+}
+
+fn show_status(status_query: &mut Query<(&mut Text, &mut PlanStatus)>, message: &str) {
+    for (mut text, mut status) in status_query.iter_mut() {
+        text.sections[0].value = message.to_string();
+        status.0.reset();
+    }
+}
+
+fn spawn_plan_status(mut cmds: Commands) {
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        },
+        DependOnState(LEVEL_STATES.to_vec()),
+        Name::new("Plan Status"),
+    ))
+    .with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 28.,
+                    color: Color::BLACK,
+                    ..default()
+                },
+            ),
+            PlanStatus(Timer::from_seconds(STATUS_SECONDS, TimerMode::Once)),
+        ));
+    });
+}
+
+fn plan_status(mut status_query: Query<(&mut Text, &mut PlanStatus)>, time: Res<Time>) {
+    for (mut text, mut status) in status_query.iter_mut() {
+        if status.0.tick(time.delta()).just_finished() {
+            text.sections[0].value.clear();
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::{
+    history::{HandleHistoryEvents, HistoryEvent},
+    momentum::{any_momentum_left, Momentum},
+    player::{player_movement, InputBuffer},
+    Dir, GameState,
+};
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Recording>()
+            .init_resource::<ReplayPlayback>()
+            .add_systems(
+                OnTransition {
+                    from: GameState::LevelTransition,
+                    to: GameState::Play,
+                },
+                start_attempt,
+            )
+            .add_systems(
+                Update,
+                play_replay
+                    .before(player_movement)
+                    .before(HandleHistoryEvents)
+                    .run_if(not(any_momentum_left()))
+                    .run_if(in_state(GameState::Play)),
+            );
+    }
+}
+
+/// Time between two steps of a replay
+const REPLAY_STEP_SECONDS: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayStep {
+    /// A step that was recorded in the history, `push` is set if it moved something
+    Move {
+        dir: Dir,
+        push: bool,
+    },
+    Undo,
+    Reset,
+}
+
+/// Everything the player did in the current attempt of a level
+#[derive(Resource, Default, Clone, Debug)]
+pub struct Recording(Vec<ReplayStep>);
+
+/// Moves and pushes of the solution, without the ones that were undone, and
+/// the number of undos
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RecordingSummary {
+    pub moves: usize,
+    pub pushes: usize,
+    pub undos: usize,
+}
+
+impl Recording {
+    pub fn record(&mut self, step: ReplayStep) {
+        self.0.push(step);
+    }
+
+    pub fn summary(&self) -> RecordingSummary {
+        // Resets can be undone too, so they keep what they cleared
+        enum Entry {
+            Move { push: bool },
+            Reset(Vec<Entry>),
+        }
+        let mut stack = Vec::new();
+        let mut undos = 0;
+        for step in self.0.iter() {
+            match step {
+                ReplayStep::Move { push, .. } => stack.push(Entry::Move { push: *push }),
+                ReplayStep::Undo => {
+                    undos += 1;
+                    if let Some(Entry::Reset(previous)) = stack.pop() {
+                        stack = previous;
+                    }
+                }
+                ReplayStep::Reset => {
+                    let previous = std::mem::take(&mut stack);
+                    stack.push(Entry::Reset(previous));
+                }
+            }
+        }
+        let moves = stack
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Move { .. }))
+            .count();
+        let pushes = stack
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Move { push: true }))
+            .count();
+        RecordingSummary {
+            moves,
+            pushes,
+            undos,
+        }
+    }
+}
+
+/// Plays a recording back instead of taking input from the player
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    steps: VecDeque<ReplayStep>,
+    timer: Timer,
+    /// Replay to play once the level started
+    pending: Option<Recording>,
+    /// The current attempt is a replay, so it doesn't count for records
+    watching: bool,
+}
+
+impl Default for ReplayPlayback {
+    fn default() -> Self {
+        Self {
+            steps: VecDeque::new(),
+            timer: Timer::from_seconds(REPLAY_STEP_SECONDS, TimerMode::Once),
+            pending: None,
+            watching: false,
+        }
+    }
+}
+
+impl ReplayPlayback {
+    /// Plays the recording the next time a level starts
+    pub fn watch(&mut self, recording: Recording) {
+        self.pending = Some(recording);
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.steps.is_empty()
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watching
+    }
+
+    /// Direction of the next step if it is a move and it is time for it
+    pub fn next_move(&mut self) -> Option<Dir> {
+        if !self.timer.finished() {
+            return None;
+        }
+        let Some(ReplayStep::Move { dir, .. }) = self.steps.front().copied() else {
+            return None;
+        };
+        self.steps.pop_front();
+        self.timer.reset();
+        Some(dir)
+    }
+}
+
+fn start_attempt(mut recording: ResMut<Recording>, mut playback: ResMut<ReplayPlayback>) {
+    recording.0.clear();
+    let pending = playback.pending.take();
+    playback.watching = pending.is_some();
+    playback.steps = pending.map(|replay| replay.0.into()).unwrap_or_default();
+    playback.timer.reset();
+}
+
+/// Paces the replay and plays undos and resets, moves are taken by `player_movement`
+fn play_replay(
+    mut playback: ResMut<ReplayPlayback>,
+    mut recording: ResMut<Recording>,
+    mut history_events: EventWriter<HistoryEvent>,
+    mut momentum_query: Query<&mut Momentum>,
+    mut input_buffer: ResMut<InputBuffer>,
+    time: Res<Time>,
+) {
+    if !playback.is_playing() {
+        return;
+    }
+    if !playback.timer.tick(time.delta()).finished() {
+        return;
+    }
+    let event = match playback.steps.front() {
+        Some(ReplayStep::Undo) => HistoryEvent::Rewind,
+        Some(ReplayStep::Reset) => HistoryEvent::Reset,
+        _ => return,
+    };
+    let step = playback.steps.pop_front().expect("Checked above");
+    playback.timer.reset();
+    recording.record(step);
+    history_events.send(event);
+    for mut momentum in momentum_query.iter_mut() {
+        momentum.take();
+    }
+    input_buffer.clear();
+}
@@ -0,0 +1,524 @@
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+use leafwing_input_manager::prelude::ActionState;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    audio::VolumeSettings,
+    cleanup::DependOnState,
+    config,
+    player::{InputBuffer, MAX_INPUT_BUFFER},
+    simulation::SimulationSettings,
+    theme::{ActiveTheme, Theme, ThemeCollection},
+    ui::NineSliceButtonText,
+    GameState, SokobanActions,
+};
+
+const SETTINGS_FILE: &str = "settings.ron";
+/// UI scales the player can cycle through
+const UI_SCALES: [f32; 5] = [0.75, 1., 1.25, 1.5, 2.];
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<DisplaySettings>()
+            .init_resource::<DisplaySettings>()
+            .init_resource::<SettingsReturn>()
+            .init_resource::<SelectedSetting>()
+            .add_event::<SettingsInteraction>()
+            .add_systems(Startup, load_settings)
+            .add_systems(OnEnter(GameState::Settings), spawn_settings_menu)
+            .add_systems(OnExit(GameState::Settings), save_settings)
+            .add_systems(
+                Update,
+                apply_display_settings.run_if(resource_changed::<DisplaySettings>()),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_buttons,
+                    ui_navigation,
+                    handle_interaction,
+                    setting_labels,
+                    render_selected_border,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Settings)),
+            );
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct DisplaySettings {
+    pub fullscreen: bool,
+    pub ui_scale: f32,
+    /// Snap to tiles instead of animating movement
+    pub reduced_motion: bool,
+    /// Name of the theme, the first one is used when it doesn't exist
+    pub theme: String,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            ui_scale: 1.,
+            reduced_motion: false,
+            theme: String::new(),
+        }
+    }
+}
+
+impl DisplaySettings {
+    fn change_ui_scale(&mut self, up: bool) {
+        let next = if up {
+            UI_SCALES.iter().position(|scale| *scale > self.ui_scale)
+        } else {
+            UI_SCALES.iter().rposition(|scale| *scale < self.ui_scale)
+        };
+        if let Some(next) = next {
+            self.ui_scale = UI_SCALES[next];
+        }
+    }
+
+    fn change_theme(&mut self, up: bool, names: &[String]) {
+        if names.is_empty() {
+            return;
+        }
+        let current = names.iter().position(|name| *name == self.theme);
+        let next = match current {
+            Some(current) if up => (current + 1) % names.len(),
+            Some(current) => (current + names.len() - 1) % names.len(),
+            None => 0,
+        };
+        self.theme = names[next].clone();
+    }
+}
+
+/// Everything on the settings screen, as stored in the config file
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct SavedSettings {
+    sfx_vol: f32,
+    music_vol: f32,
+    speed: f32,
+    input_buffer: usize,
+    fullscreen: bool,
+    ui_scale: f32,
+    reduced_motion: bool,
+    theme: String,
+}
+
+impl Default for SavedSettings {
+    fn default() -> Self {
+        let volume = VolumeSettings::default();
+        let simulation = SimulationSettings::default();
+        let display = DisplaySettings::default();
+        Self {
+            sfx_vol: volume.sfx_vol,
+            music_vol: volume.music_vol,
+            speed: simulation.speed,
+            input_buffer: InputBuffer::default().capacity,
+            fullscreen: display.fullscreen,
+            ui_scale: display.ui_scale,
+            reduced_motion: display.reduced_motion,
+            theme: display.theme,
+        }
+    }
+}
+
+fn load_settings(
+    mut volume: ResMut<VolumeSettings>,
+    mut simulation: ResMut<SimulationSettings>,
+    mut input_buffer: ResMut<InputBuffer>,
+    mut display: ResMut<DisplaySettings>,
+) {
+    let saved: SavedSettings = config::load_or_default(SETTINGS_FILE);
+    volume.sfx_vol = saved.sfx_vol.clamp(0., 1.);
+    volume.music_vol = saved.music_vol.clamp(0., 1.);
+    simulation.speed = saved.speed.clamp(0.1, 10.);
+    input_buffer.capacity = saved.input_buffer.min(MAX_INPUT_BUFFER);
+    display.fullscreen = saved.fullscreen;
+    display.ui_scale = saved.ui_scale.clamp(0.5, 3.);
+    display.reduced_motion = saved.reduced_motion;
+    display.theme = saved.theme;
+}
+
+fn save_settings(
+    volume: Res<VolumeSettings>,
+    simulation: Res<SimulationSettings>,
+    input_buffer: Res<InputBuffer>,
+    display: Res<DisplaySettings>,
+) {
+    let saved = SavedSettings {
+        sfx_vol: volume.sfx_vol,
+        music_vol: volume.music_vol,
+        speed: simulation.speed,
+        input_buffer: input_buffer.capacity,
+        fullscreen: display.fullscreen,
+        ui_scale: display.ui_scale,
+        reduced_motion: display.reduced_motion,
+        theme: display.theme.clone(),
+    };
+    config::save_or_warn(SETTINGS_FILE, &saved);
+}
+
+fn apply_display_settings(
+    display: Res<DisplaySettings>,
+    mut ui_scale: ResMut<UiScale>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    ui_scale.0 = display.ui_scale as f64;
+    let mode = if display.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+    for mut window in window_query.iter_mut() {
+        if window.mode != mode {
+            window.mode = mode;
+        }
+    }
+}
+
+/// State the settings screen goes back to, it can be opened from the main and pause menu
+#[derive(Resource, Deref, DerefMut)]
+pub struct SettingsReturn(pub GameState);
+
+impl Default for SettingsReturn {
+    fn default() -> Self {
+        Self(GameState::MainMenu)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Setting {
+    MusicVolume,
+    SfxVolume,
+    Speed,
+    InputBuffer,
+    Fullscreen,
+    UiScale,
+    ReducedMotion,
+    Theme,
+}
+
+const ALL_SETTINGS: [Setting; 8] = [
+    Setting::MusicVolume,
+    Setting::SfxVolume,
+    Setting::Speed,
+    Setting::InputBuffer,
+    Setting::Fullscreen,
+    Setting::UiScale,
+    Setting::ReducedMotion,
+    Setting::Theme,
+];
+
+impl Setting {
+    fn name(&self) -> &'static str {
+        match self {
+            Setting::MusicVolume => "Music Volume",
+            Setting::SfxVolume => "Sound Volume",
+            Setting::Speed => "Speed",
+            Setting::InputBuffer => "Input Buffer",
+            Setting::Fullscreen => "Fullscreen",
+            Setting::UiScale => "UI Scale",
+            Setting::ReducedMotion => "Reduced Motion",
+            Setting::Theme => "Theme",
+        }
+    }
+
+    fn value(
+        &self,
+        volume: &VolumeSettings,
+        simulation: &SimulationSettings,
+        input_buffer: &InputBuffer,
+        display: &DisplaySettings,
+    ) -> String {
+        match self {
+            Setting::MusicVolume => format!("{:.0}%", volume.music_vol * 100.),
+            Setting::SfxVolume => format!("{:.0}%", volume.sfx_vol * 100.),
+            Setting::Speed => format!("{}x", simulation.speed),
+            // Moves pressed while things are still moving, 0 ignores them
+            Setting::InputBuffer if input_buffer.capacity == 0 => on_off(false),
+            Setting::InputBuffer => input_buffer.capacity.to_string(),
+            Setting::Fullscreen => on_off(display.fullscreen),
+            Setting::UiScale => format!("{}x", display.ui_scale),
+            Setting::ReducedMotion => on_off(display.reduced_motion),
+            Setting::Theme => display.theme.clone(),
+        }
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "On" } else { "Off" }.to_string()
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum SettingsButton {
+    Decrease(Setting),
+    Increase(Setting),
+    Back,
+}
+
+impl From<SettingsButton> for String {
+    fn from(value: SettingsButton) -> Self {
+        match value {
+            SettingsButton::Decrease(_) => "<",
+            SettingsButton::Increase(_) => ">",
+            SettingsButton::Back => "Back",
+        }
+        .to_string()
+    }
+}
+
+/// Shows the current value of a setting, framed while its row is selected
+#[derive(Component)]
+struct SettingValue(Setting);
+
+/// Row selected with the navigation actions, the one after the last setting is `Back`
+#[derive(Resource, Default, Deref, DerefMut)]
+struct SelectedSetting(usize);
+
+#[derive(Event, Deref, DerefMut)]
+struct SettingsInteraction(pub SettingsButton);
+
+fn spawn_settings_menu(
+    mut cmds: Commands,
+    theme: Res<ActiveTheme>,
+    mut selected: ResMut<SelectedSetting>,
+) {
+    **selected = 0;
+
+    let button_texture = theme.button.clone_weak();
+    let small_button_style = Style {
+        width: Val::Px(32.0),
+        height: Val::Px(32.0),
+        margin: UiRect::all(Val::Px(4.)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        border: UiRect::all(Val::Px(2.)),
+        ..default()
+    };
+    let text_style = TextStyle {
+        font_size: 20.,
+        color: Color::BLACK,
+        ..default()
+    };
+
+    let root = cmds
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            DependOnState::single(GameState::Settings),
+            Name::new("Settings Menu"),
+        ))
+        .id();
+
+    for setting in ALL_SETTINGS {
+        let row = cmds
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .id();
+        cmds.entity(root).add_child(row);
+        cmds.entity(row).with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(setting.name(), text_style.clone()).with_style(Style {
+                    width: Val::Px(150.),
+                    ..default()
+                }),
+            );
+        });
+        cmds.add(NineSliceButtonText {
+            button: SettingsButton::Decrease(setting),
+            style: small_button_style.clone(),
+            texture: button_texture.clone_weak(),
+            parent: row,
+        });
+        cmds.entity(row).with_children(|parent| {
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(80.),
+                            height: Val::Px(32.),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            border: UiRect::all(Val::Px(2.)),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    SettingValue(setting),
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("", text_style.clone()));
+                });
+        });
+        cmds.add(NineSliceButtonText {
+            button: SettingsButton::Increase(setting),
+            style: small_button_style.clone(),
+            texture: button_texture.clone_weak(),
+            parent: row,
+        });
+    }
+
+    cmds.add(NineSliceButtonText {
+        button: SettingsButton::Back,
+        style: Style {
+            width: Val::Px(150.0),
+            height: Val::Px(65.0),
+            margin: UiRect::all(Val::Px(10.)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            border: UiRect::all(Val::Px(2.)),
+            ..default()
+        },
+        texture: button_texture.clone_weak(),
+        parent: root,
+    });
+}
+
+fn handle_buttons(
+    buttons: Query<(&SettingsButton, &Interaction), Changed<Interaction>>,
+    mut event_writer: EventWriter<SettingsInteraction>,
+) {
+    buttons.iter().for_each(|button| {
+        if let (button, Interaction::Pressed) = button {
+            event_writer.send(SettingsInteraction(*button));
+        }
+    });
+}
+
+fn ui_navigation(
+    navigation_actions: Query<&ActionState<SokobanActions>>,
+    mut selected: ResMut<SelectedSetting>,
+    mut event_writer: EventWriter<SettingsInteraction>,
+) {
+    let Ok(navigation_actions) = navigation_actions.get_single() else {
+        return;
+    };
+
+    let rows = ALL_SETTINGS.len() + 1;
+    if navigation_actions.just_pressed(SokobanActions::UiNavUp) {
+        **selected = (**selected + rows - 1) % rows;
+    }
+    if navigation_actions.just_pressed(SokobanActions::UiNavDown) {
+        **selected = (**selected + 1) % rows;
+    }
+    let setting = ALL_SETTINGS.get(**selected).copied();
+    if let Some(setting) = setting {
+        if navigation_actions.just_pressed(SokobanActions::UiNavLeft) {
+            event_writer.send(SettingsInteraction(SettingsButton::Decrease(setting)));
+        }
+        if navigation_actions.just_pressed(SokobanActions::UiNavRight)
+            || navigation_actions.just_pressed(SokobanActions::UiNavSelect)
+        {
+            event_writer.send(SettingsInteraction(SettingsButton::Increase(setting)));
+        }
+    } else if navigation_actions.just_pressed(SokobanActions::UiNavSelect) {
+        event_writer.send(SettingsInteraction(SettingsButton::Back));
+    }
+    if navigation_actions.just_pressed(SokobanActions::Escape) {
+        event_writer.send(SettingsInteraction(SettingsButton::Back));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_interaction(
+    mut events: EventReader<SettingsInteraction>,
+    mut volume: ResMut<VolumeSettings>,
+    mut simulation: ResMut<SimulationSettings>,
+    mut input_buffer: ResMut<InputBuffer>,
+    mut display: ResMut<DisplaySettings>,
+    theme_collection: Res<ThemeCollection>,
+    themes: Res<Assets<Theme>>,
+    settings_return: Res<SettingsReturn>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    for ev in events.read() {
+        let (setting, up) = match **ev {
+            SettingsButton::Decrease(setting) => (setting, false),
+            SettingsButton::Increase(setting) => (setting, true),
+            SettingsButton::Back => {
+                game_state.set(**settings_return);
+                continue;
+            }
+        };
+        let steps = if up { 1. } else { -1. };
+        match setting {
+            Setting::MusicVolume => volume.change_music_vol(steps),
+            Setting::SfxVolume => volume.change_sfx_vol(steps),
+            Setting::Speed if up => simulation.change_speed(),
+            Setting::Speed => simulation.slow_down(),
+            Setting::InputBuffer => input_buffer.change_capacity(up),
+            Setting::Fullscreen => display.fullscreen = !display.fullscreen,
+            Setting::UiScale => display.change_ui_scale(up),
+            Setting::ReducedMotion => display.reduced_motion = !display.reduced_motion,
+            Setting::Theme => display.change_theme(up, &theme_collection.names(&themes)),
+        }
+    }
+}
+
+fn setting_labels(
+    volume: Res<VolumeSettings>,
+    simulation: Res<SimulationSettings>,
+    input_buffer: Res<InputBuffer>,
+    display: Res<DisplaySettings>,
+    values: Query<(&SettingValue, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (setting, children) in values.iter() {
+        let label = setting
+            .0
+            .value(&volume, &simulation, &input_buffer, &display);
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+}
+
+fn render_selected_border(
+    selected: Res<SelectedSetting>,
+    mut values: Query<(&SettingValue, &mut BorderColor)>,
+    mut buttons: Query<(&SettingsButton, &mut BorderColor), Without<SettingValue>>,
+) {
+    let setting = ALL_SETTINGS.get(**selected);
+    for (value, mut border_color) in values.iter_mut() {
+        *border_color = if Some(&value.0) == setting {
+            BorderColor(Color::RED)
+        } else {
+            BorderColor(Color::NONE)
+        };
+    }
+    for (button, mut border_color) in buttons.iter_mut() {
+        *border_color = if *button == SettingsButton::Back && setting.is_none() {
+            BorderColor(Color::RED)
+        } else {
+            BorderColor(Color::NONE)
+        };
+    }
+}
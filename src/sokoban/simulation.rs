@@ -0,0 +1,191 @@
+use bevy::{log, prelude::*};
+use leafwing_input_manager::prelude::ActionState;
+
+use super::{
+    cleanup::DependOnState,
+    history::HandleHistoryEvents,
+    momentum::{any_momentum_left, Momentum},
+    GameState, SokobanActions, LEVEL_STATES,
+};
+
+/// Fixed steps per second at 1x speed
+pub const BASE_TICK_RATE: f64 = 8.;
+/// Speed multiplier while fast forward is held
+pub const FAST_FORWARD: f64 = 4.;
+/// Speeds the player can cycle through
+pub const SPEEDS: [f32; 5] = [0.5, 1., 2., 3., 4.];
+/// Upper bound of steps resolved in a single frame, rubber can bounce balls forever
+const MAX_INSTANT_STEPS: usize = 1000;
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<SimulationSettings>()
+            .init_resource::<SimulationSettings>()
+            .insert_resource(Time::<Fixed>::from_hz(BASE_TICK_RATE))
+            .add_systems(
+                OnTransition {
+                    from: GameState::LevelTransition,
+                    to: GameState::Play,
+                },
+                spawn_speed_hud,
+            )
+            .add_systems(
+                Update,
+                (
+                    (handle_speed_actions, update_timestep).chain(),
+                    speed_hud,
+                    resolve_instantly
+                        .after(HandleHistoryEvents)
+                        .run_if(instant_enabled)
+                        .run_if(any_momentum_left()),
+                )
+                    .run_if(in_state(GameState::Play)),
+            );
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct SimulationSettings {
+    pub speed: f32,
+    /// Resolve all momentum in a single frame
+    pub instant: bool,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.,
+            instant: false,
+        }
+    }
+}
+
+impl SimulationSettings {
+    pub fn change_speed(&mut self) {
+        let next = SPEEDS
+            .iter()
+            .position(|speed| *speed > self.speed)
+            .unwrap_or(0);
+        self.speed = SPEEDS[next];
+    }
+
+    pub fn slow_down(&mut self) {
+        let previous = SPEEDS
+            .iter()
+            .rposition(|speed| *speed < self.speed)
+            .unwrap_or(SPEEDS.len() - 1);
+        self.speed = SPEEDS[previous];
+    }
+
+    pub fn toggle_instant(&mut self) {
+        self.instant = !self.instant;
+    }
+}
+
+impl std::fmt::Display for SimulationSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.instant {
+            write!(f, "Instant")
+        } else {
+            write!(f, "{}x", self.speed)
+        }
+    }
+}
+
+#[derive(Component)]
+struct SpeedCounter;
+
+fn instant_enabled(settings: Res<SimulationSettings>) -> bool {
+    settings.instant
+}
+
+fn handle_speed_actions(
+    actions: Query<&ActionState<SokobanActions>>,
+    mut settings: ResMut<SimulationSettings>,
+) {
+    let Ok(actions) = actions.get_single() else {
+        return;
+    };
+    if actions.just_pressed(SokobanActions::ChangeSpeed) {
+        settings.change_speed();
+    }
+    if actions.just_pressed(SokobanActions::ToggleInstant) {
+        settings.toggle_instant();
+    }
+}
+
+fn update_timestep(
+    actions: Query<&ActionState<SokobanActions>>,
+    settings: Res<SimulationSettings>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    let fast_forward = actions
+        .get_single()
+        .is_ok_and(|actions| actions.pressed(SokobanActions::FastForward));
+    let mut hz = BASE_TICK_RATE * settings.speed as f64;
+    if fast_forward {
+        hz *= FAST_FORWARD;
+    }
+    if (fixed_time.timestep().as_secs_f64() - 1. / hz).abs() > f64::EPSILON {
+        fixed_time.set_timestep_hz(hz);
+    }
+}
+
+// Runs the same fixed steps as normal speed, just all of them right now
+fn resolve_instantly(world: &mut World) {
+    let mut momentum_query = world.query::<&Momentum>();
+    for _ in 0..MAX_INSTANT_STEPS {
+        if !momentum_query
+            .iter(world)
+            .any(|momentum| momentum.is_some())
+        {
+            return;
+        }
+        world.run_schedule(FixedUpdate);
+    }
+    log::warn!("Momentum still left after {MAX_INSTANT_STEPS} instant steps");
+}
+
+fn spawn_speed_hud(mut cmds: Commands, settings: Res<SimulationSettings>) {
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                right: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        },
+        DependOnState(LEVEL_STATES.to_vec()),
+        Name::new("Speed HUD"),
+    ))
+    .with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                settings.to_string(),
+                TextStyle {
+                    font_size: 28.,
+                    color: Color::BLACK,
+                    ..default()
+                },
+            ),
+            SpeedCounter,
+        ));
+    });
+}
+
+fn speed_hud(
+    settings: Res<SimulationSettings>,
+    mut counter_query: Query<&mut Text, With<SpeedCounter>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for mut text in counter_query.iter_mut() {
+        text.sections[0].value = settings.to_string();
+    }
+}
@@ -0,0 +1,368 @@
+use bevy::{
+    asset::{AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use bevy_asset_loader::prelude::AssetCollection;
+use bevy_nine_slice_ui::NineSliceTexture;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::{
+    animation::{BallAnimations, Clip, PlayerAnimations},
+    autotile::Autotile,
+    grid::GridConfig,
+    level::TileKind,
+    settings::DisplaySettings,
+    GameState,
+};
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_asset_loader(ThemeLoader)
+            .init_asset::<Theme>()
+            .init_resource::<ActiveTheme>()
+            .add_systems(OnExit(GameState::AssetLoading), apply_theme)
+            .add_systems(
+                Update,
+                (
+                    apply_theme
+                        .run_if(resource_exists::<ThemeCollection>())
+                        .run_if(
+                            resource_changed::<DisplaySettings>()
+                                .or_else(on_event::<AssetEvent<Theme>>()),
+                        ),
+                    retexture_buttons.run_if(resource_changed::<ActiveTheme>()),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource, Reflect, Default, Debug, AssetCollection)]
+#[reflect(Resource)]
+pub struct ThemeCollection {
+    /// The first theme is used when the saved one doesn't exist
+    #[asset(paths("themes/classic.theme", "themes/frost.theme"), collection(typed))]
+    pub themes: Vec<Handle<Theme>>,
+}
+
+impl ThemeCollection {
+    /// Names of the loaded themes, in the order they are listed in
+    pub fn names(&self, themes: &Assets<Theme>) -> Vec<String> {
+        self.themes
+            .iter()
+            .filter_map(|handle| themes.get(handle))
+            .map(|theme| theme.name.clone())
+            .collect()
+    }
+}
+
+/// Everything that gives the game its look, artists can ship new themes without touching code
+#[derive(TypePath, TypeUuid, Asset, Clone, Default, Debug, PartialEq)]
+#[uuid = "b4c1e7d2-9a3f-4e68-8d5b-2f7a0c9e1b54"]
+pub struct Theme {
+    pub name: String,
+    #[dependency]
+    pub grid: Handle<GridConfig>,
+    /// Tileset of the floor and everything built into it
+    #[dependency]
+    pub tiles: Handle<Image>,
+    #[dependency]
+    pub walls: Handle<Image>,
+    /// Which wall tile is drawn for which neighbours
+    #[dependency]
+    pub wall_autotile: Handle<Autotile>,
+    #[dependency]
+    pub player: Handle<Image>,
+    /// Frames cut out of `player`
+    pub player_sheet: Handle<TextureAtlas>,
+    pub player_animations: PlayerAnimations,
+    #[dependency]
+    pub ball: Handle<Image>,
+    pub ball_sheet: Handle<TextureAtlas>,
+    pub ball_animations: BallAnimations,
+    #[dependency]
+    pub crate_texture: Handle<Image>,
+    #[dependency]
+    pub goal: Handle<Image>,
+    #[dependency]
+    pub key: Handle<Image>,
+    #[dependency]
+    pub lock: Handle<Image>,
+    #[dependency]
+    pub mirror: Handle<Image>,
+    /// Nine-slice texture of the UI buttons
+    #[dependency]
+    pub button: Handle<Image>,
+    pub clear_color: Color,
+    pub tile_indices: TileIndices,
+}
+
+/// Index into the floor tileset for every kind of tile drawn from it
+#[derive(Deserialize, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct TileIndices {
+    pub floor: u32,
+    pub sand: u32,
+    pub void: u32,
+    pub rubber: u32,
+    pub lamp_off: u32,
+    pub lamp_on: u32,
+    pub ice: u32,
+    pub switch_off: u32,
+    pub switch_on: u32,
+    pub emitter: u32,
+    pub receiver: u32,
+}
+
+impl Default for TileIndices {
+    fn default() -> Self {
+        Self {
+            floor: 0,
+            sand: 1,
+            void: 2,
+            rubber: 3,
+            lamp_off: 4,
+            lamp_on: 5,
+            ice: 7,
+            switch_off: 8,
+            switch_on: 9,
+            emitter: 10,
+            receiver: 4,
+        }
+    }
+}
+
+impl TileIndices {
+    /// Tile under `kind`, things that are sprites of their own stand on floor
+    pub fn get(&self, kind: TileKind) -> u32 {
+        match kind {
+            TileKind::Sand => self.sand,
+            TileKind::Void => self.void,
+            TileKind::Rubber => self.rubber,
+            TileKind::LampOff => self.lamp_off,
+            TileKind::LampOn => self.lamp_on,
+            TileKind::Ice => self.ice,
+            TileKind::Switch => self.switch_off,
+            TileKind::Emitter(_) => self.emitter,
+            TileKind::Receiver => self.receiver,
+            TileKind::Wall
+            | TileKind::Floor
+            | TileKind::Ball
+            | TileKind::Player
+            | TileKind::Goal
+            | TileKind::BallGoal
+            | TileKind::Key
+            | TileKind::Lock
+            | TileKind::Crate
+            | TileKind::CrateGoal
+            | TileKind::CrateOnCrateGoal
+            | TileKind::MirrorForward
+            | TileKind::MirrorBackward => self.floor,
+        }
+    }
+
+    pub fn lamp(&self, on: bool) -> u32 {
+        if on {
+            self.lamp_on
+        } else {
+            self.lamp_off
+        }
+    }
+
+    pub fn switch(&self, on: bool) -> u32 {
+        if on {
+            self.switch_on
+        } else {
+            self.switch_off
+        }
+    }
+}
+
+/// The theme picked in the settings. UI and background switch right away,
+/// the level picks it up the next time it is spawned.
+#[derive(Resource, Default, Deref)]
+pub struct ActiveTheme(Theme);
+
+fn apply_theme(
+    mut active: ResMut<ActiveTheme>,
+    mut clear_color: ResMut<ClearColor>,
+    mut display: ResMut<DisplaySettings>,
+    collection: Res<ThemeCollection>,
+    themes: Res<Assets<Theme>>,
+) {
+    let loaded = || {
+        collection
+            .themes
+            .iter()
+            .filter_map(|handle| themes.get(handle))
+    };
+    let Some(theme) = loaded()
+        .find(|theme| theme.name == display.theme)
+        .or_else(|| loaded().next())
+    else {
+        return;
+    };
+    if display.theme != theme.name {
+        display.theme = theme.name.clone();
+    }
+    if active.0 != *theme {
+        active.0 = theme.clone();
+        clear_color.0 = theme.clear_color;
+    }
+}
+
+fn retexture_buttons(theme: Res<ActiveTheme>, mut buttons: Query<&mut NineSliceTexture>) {
+    for mut texture in buttons.iter_mut() {
+        *texture = NineSliceTexture::new(theme.button.clone_weak());
+    }
+}
+
+#[derive(Deserialize)]
+struct StringTheme {
+    name: String,
+    grid: String,
+    tiles: String,
+    walls: String,
+    wall_autotile: String,
+    player: StringSpriteSheet,
+    #[serde(default)]
+    player_animations: PlayerAnimations,
+    ball: StringSpriteSheet,
+    #[serde(default)]
+    ball_animations: BallAnimations,
+    #[serde(rename = "crate")]
+    crate_texture: String,
+    goal: String,
+    key: String,
+    lock: String,
+    mirror: String,
+    button: String,
+    /// Red, green and blue between 0 and 1
+    clear_color: (f32, f32, f32),
+    #[serde(default)]
+    tile_indices: TileIndices,
+}
+
+/// Image cut into frames of the same size, numbered row by row
+#[derive(Deserialize)]
+struct StringSpriteSheet {
+    texture: String,
+    /// Width and height of a frame in pixels
+    frame_size: (f32, f32),
+    columns: usize,
+    rows: usize,
+}
+
+impl StringSpriteSheet {
+    /// Fails if one of the clips shows a frame the sheet doesn't have
+    fn check_frames<'a>(
+        &self,
+        clips: impl IntoIterator<Item = &'a Clip>,
+    ) -> Result<(), ThemeLoaderError> {
+        let frames = self.columns * self.rows;
+        match clips
+            .into_iter()
+            .flat_map(|clip| clip.frames.iter())
+            .find(|frame| **frame >= frames)
+        {
+            Some(frame) => Err(ThemeLoaderError::FrameOutOfRange {
+                texture: self.texture.clone(),
+                frame: *frame,
+                frames,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn load(
+        self,
+        label: &str,
+        load_context: &mut LoadContext,
+    ) -> (Handle<Image>, Handle<TextureAtlas>) {
+        let texture = load_context.load(self.texture);
+        let atlas = TextureAtlas::from_grid(
+            texture.clone(),
+            Vec2::from(self.frame_size),
+            self.columns,
+            self.rows,
+            None,
+            None,
+        );
+        (
+            texture,
+            load_context.add_labeled_asset(label.to_string(), atlas),
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ThemeLoaderError {
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse the ron: {0}")]
+    RonError(#[from] ron::error::SpannedError),
+    #[error("Frame {frame} is not in {texture}, it has {frames} frames")]
+    FrameOutOfRange {
+        texture: String,
+        frame: usize,
+        frames: usize,
+    },
+}
+
+impl AssetLoader for ThemeLoader {
+    type Asset = Theme;
+    type Settings = ();
+    type Error = ThemeLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut bevy::asset::io::Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, std::result::Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let theme = ron::de::from_bytes::<StringTheme>(&bytes)?;
+            theme.player.check_frames(theme.player_animations.clips())?;
+            theme.ball.check_frames(theme.ball_animations.clips())?;
+            let (r, g, b) = theme.clear_color;
+            let (player, player_sheet) = theme.player.load("player", load_context);
+            let (ball, ball_sheet) = theme.ball.load("ball", load_context);
+
+            Ok(Theme {
+                name: theme.name,
+                grid: load_context.load(theme.grid),
+                tiles: load_context.load(theme.tiles),
+                walls: load_context.load(theme.walls),
+                wall_autotile: load_context.load(theme.wall_autotile),
+                player,
+                player_sheet,
+                player_animations: theme.player_animations,
+                ball,
+                ball_sheet,
+                ball_animations: theme.ball_animations,
+                crate_texture: load_context.load(theme.crate_texture),
+                goal: load_context.load(theme.goal),
+                key: load_context.load(theme.key),
+                lock: load_context.load(theme.lock),
+                mirror: load_context.load(theme.mirror),
+                button: load_context.load(theme.button),
+                clear_color: Color::rgb(r, g, b),
+                tile_indices: theme.tile_indices,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme"]
+    }
+}
@@ -1,98 +1,175 @@
-use std::ops::AddAssign;
-
 use bevy::{ecs::system::Command, prelude::*};
 use bevy_ecs_tilemap::tiles::TileTextureIndex;
 
 use super::{
     ball::Ball,
+    crates::Crate,
     entity::DespawnSokobanEntityCommand,
-    level_select::CurrentLevel,
+    history::HandleHistoryEvents,
+    laser::LaserReceiver,
     momentum::{any_momentum_left, apply_momentum, transfer_momentum, Momentum},
+    motion::SpawnFall,
     player::Player,
-    AssetsCollection, GameState, Pos,
+    theme::ActiveTheme,
+    GameState, Pos, SokobanEvent,
 };
 
 pub struct TileBehaviourPlugin;
 
 impl Plugin for TileBehaviourPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            FixedUpdate,
-            (
-                rubber,
-                lamp_interaction.before(transfer_momentum),
-                lamp_visual.after(lamp_interaction),
-                void.after(transfer_momentum).before(apply_momentum),
-                sand.after(apply_momentum),
-                win.run_if(not(any_momentum_left()).and_then(goal.and_then(lamp()))),
+        app.add_event::<ToggleSwitch>()
+            .add_systems(
+                Update,
+                (toggle_switch.after(HandleHistoryEvents), switch_visual)
+                    .run_if(in_state(GameState::Play)),
             )
-                .run_if(in_state(GameState::Play)),
-        );
+            .add_systems(
+                FixedUpdate,
+                (
+                    rubber.before(transfer_momentum),
+                    lamp_interaction.before(transfer_momentum),
+                    lamp_visual.after(lamp_interaction),
+                    void.after(transfer_momentum).before(apply_momentum),
+                    sand.after(apply_momentum),
+                    win.run_if(not(any_momentum_left()).and_then(goal.and_then(lamp()))),
+                )
+                    .run_if(in_state(GameState::Play)),
+            );
     }
 }
 
 #[derive(Component)]
 pub struct Sand;
 #[derive(Component)]
+pub struct Ice;
+#[derive(Component)]
 pub struct Goal;
+/// Goal that only counts as covered by a crate
+#[derive(Component)]
+pub struct CrateGoal;
 #[derive(Component)]
 pub struct Rubber;
 #[derive(Component)]
 pub struct Void;
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Lamp(pub bool);
+/// Lamps and switches sharing a group toggle together
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LampGroup(pub u8);
+#[derive(Component, Clone)]
+pub struct Switch(pub bool);
+
+/// Sent when the player walks into a switch.
+/// Handled after history has been recorded so the step can be undone.
+#[derive(Event)]
+pub struct ToggleSwitch(pub Entity);
 
 fn lamp_interaction(
-    mut lamp_query: Query<(&Pos, &mut Lamp)>,
+    mut lamp_query: Query<(&Pos, &mut Lamp, Option<&LampGroup>), Without<LaserReceiver>>,
     momentum_query: Query<(&Pos, &Momentum), Without<Player>>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
 ) {
     for (pos, momentum) in momentum_query.iter() {
         if let Some(dir) = **momentum {
             let mut dest = *pos;
             dest.add_dir(dir);
 
-            for (lamp_pos, mut lamp) in lamp_query.iter_mut() {
-                if *lamp_pos == dest {
-                    lamp.0 = !lamp.0;
-                    break;
-                }
-            }
+            let Some(group) = lamp_query
+                .iter()
+                .find(|(lamp_pos, _, _)| **lamp_pos == dest)
+                .map(|(_, _, group)| group.copied())
+            else {
+                continue;
+            };
+            toggle_lamps(&mut lamp_query, dest, group);
+            sokoban_events.send(SokobanEvent::LampToggled);
         }
     }
 }
 
-fn lamp_visual(mut lamp_query: Query<(&mut TileTextureIndex, &Lamp), Changed<Lamp>>) {
-    for (mut id, lamp_state) in lamp_query.iter_mut() {
-        if lamp_state.0 {
-            id.0 = 6;
-        } else {
-            id.0 = 5;
+// Toggles the lamp at `pos` and every lamp wired to `group`
+fn toggle_lamps(
+    lamp_query: &mut Query<(&Pos, &mut Lamp, Option<&LampGroup>), Without<LaserReceiver>>,
+    pos: Pos,
+    group: Option<LampGroup>,
+) {
+    for (lamp_pos, mut lamp, lamp_group) in lamp_query.iter_mut() {
+        let wired = group.is_some() && lamp_group.copied() == group;
+        if wired || *lamp_pos == pos {
+            lamp.0 = !lamp.0;
         }
     }
 }
 
-fn win(mut current_level: ResMut<CurrentLevel>, mut next_state: ResMut<NextState<GameState>>) {
-    current_level.add_assign(1);
-    next_state.set(GameState::LevelTransition);
+fn toggle_switch(
+    mut toggle_events: EventReader<ToggleSwitch>,
+    mut switch_query: Query<(&Pos, &mut Switch, Option<&LampGroup>)>,
+    mut lamp_query: Query<(&Pos, &mut Lamp, Option<&LampGroup>), Without<LaserReceiver>>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
+) {
+    for ToggleSwitch(entity) in toggle_events.read() {
+        let Ok((pos, mut switch, group)) = switch_query.get_mut(*entity) else {
+            continue;
+        };
+        switch.0 = !switch.0;
+        toggle_lamps(&mut lamp_query, *pos, group.copied());
+        sokoban_events.send(SokobanEvent::LampToggled);
+    }
+}
+
+fn switch_visual(
+    mut switch_query: Query<(&mut TileTextureIndex, &Switch), Changed<Switch>>,
+    theme: Res<ActiveTheme>,
+) {
+    for (mut id, switch) in switch_query.iter_mut() {
+        id.0 = theme.tile_indices.switch(switch.0);
+    }
+}
+
+fn lamp_visual(
+    mut lamp_query: Query<(&mut TileTextureIndex, &Lamp), Changed<Lamp>>,
+    theme: Res<ActiveTheme>,
+) {
+    for (mut id, lamp_state) in lamp_query.iter_mut() {
+        id.0 = theme.tile_indices.lamp(lamp_state.0);
+    }
+}
+
+fn win(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
+) {
+    sokoban_events.send(SokobanEvent::LevelComplete);
+    next_state.set(GameState::LevelComplete);
 }
 
 fn lamp() -> impl FnMut(Query<&Lamp>) -> bool + Clone {
     move |query: Query<&Lamp>| query.iter().all(|lamp| lamp.0)
 }
 
-fn goal(balls: Query<&Pos, With<Ball>>, goals: Query<&Pos, With<Goal>>) -> bool {
-    goals
-        .iter()
-        .all(|goal| balls.iter().any(|ball| ball == goal))
+fn goal(
+    boxes: Query<&Pos, Or<(With<Ball>, With<Crate>)>>,
+    crates: Query<&Pos, With<Crate>>,
+    goals: Query<(&Pos, Has<CrateGoal>), With<Goal>>,
+) -> bool {
+    goals.iter().all(|(goal, crate_only)| {
+        if crate_only {
+            crates.iter().any(|crate_pos| crate_pos == goal)
+        } else {
+            boxes.iter().any(|box_pos| box_pos == goal)
+        }
+    })
 }
 
 fn sand(
     sand_query: Query<&Pos, With<Sand>>,
     mut momentum_query: Query<(&Pos, &mut Momentum), (Without<Player>, Changed<Pos>)>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
 ) {
     for (pos, mut momentum) in momentum_query.iter_mut() {
-        if sand_query.iter().any(|sand_pos| sand_pos == pos) {
-            momentum.take();
+        if sand_query.iter().any(|sand_pos| sand_pos == pos) && momentum.take().is_some() {
+            sokoban_events.send(SokobanEvent::SandStop);
         }
     }
 }
@@ -100,6 +177,7 @@ fn sand(
 fn rubber(
     rubber_query: Query<&Pos, With<Rubber>>,
     mut momentum_query: Query<(&Pos, &mut Momentum)>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
 ) {
     for (pos, mut momentum) in momentum_query.iter_mut() {
         if let Some(dir) = **momentum {
@@ -108,6 +186,7 @@ fn rubber(
 
             if rubber_query.iter().any(|rubber_pos| *rubber_pos == dest) {
                 momentum.replace(dir.opposite());
+                sokoban_events.send(SokobanEvent::RubberBounce);
             }
         }
     }
@@ -117,10 +196,13 @@ fn void(
     mut cmds: Commands,
     void_query: Query<&Pos, With<Void>>,
     sokoban_query: Query<(Entity, &Pos), Without<Void>>,
+    mut sokoban_events: EventWriter<SokobanEvent>,
 ) {
     for (entity, pos) in sokoban_query.iter() {
         if void_query.iter().any(|void_pos| void_pos == pos) {
+            cmds.add(SpawnFall(entity));
             cmds.add(DespawnSokobanEntityCommand(entity));
+            sokoban_events.send(SokobanEvent::EntityInVoid);
         }
     }
 }
@@ -128,6 +210,7 @@ fn void(
 pub struct SpawnGoal {
     pos: Pos,
     tilemap_entity: Entity,
+    crate_only: bool,
 }
 
 impl SpawnGoal {
@@ -135,18 +218,27 @@ impl SpawnGoal {
         Self {
             pos,
             tilemap_entity,
+            crate_only: false,
+        }
+    }
+
+    pub fn crate_only(pos: Pos, tilemap_entity: Entity) -> Self {
+        Self {
+            pos,
+            tilemap_entity,
+            crate_only: true,
         }
     }
 }
 
 impl Command for SpawnGoal {
     fn apply(self, world: &mut World) {
-        let texture = world.resource::<AssetsCollection>().goal.clone();
+        let texture = world.resource::<ActiveTheme>().goal.clone();
 
         world
             .entity_mut(self.tilemap_entity)
             .with_children(|child_builder| {
-                child_builder.spawn((
+                let mut goal = child_builder.spawn((
                     Name::new("Goal"),
                     Goal,
                     self.pos,
@@ -156,6 +248,16 @@ impl Command for SpawnGoal {
                         ..default()
                     },
                 ));
+                if self.crate_only {
+                    goal.insert((
+                        Name::new("Crate Goal"),
+                        CrateGoal,
+                        Sprite {
+                            color: Color::rgb(0.8, 0.55, 0.3),
+                            ..default()
+                        },
+                    ));
+                }
             });
     }
 }